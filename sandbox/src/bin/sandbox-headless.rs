@@ -0,0 +1,373 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use sandbox::ParticleType;
+use sandbox::SandBox;
+use sandbox::image::Image;
+use sandbox::image::write_image;
+use sandbox::level::Legend;
+use sandbox::materials::Materials;
+use sandbox::overlay::Overlay;
+use sandbox::post_process::Effect;
+use sandbox::post_process::PostProcess;
+use sandbox::recording::Recorder;
+use sandbox::tools::Mode;
+use sandbox::tools::Shape;
+use sandbox::tools::Tool;
+
+const USAGE: &str = "usage: sandbox-headless [--script FILE] [--ticks N] [--seed N] [--size W H] [--threads N] \
+                     [--stats-every N] [--frame FILE] [--load FILE.sbx] [--save FILE.sbx] [--materials FILE] \
+                     [--level IMAGE] [--legend FILE] [--frames DIR] [--record FILE.gif] \
+                     [--from TICK] [--to TICK] [--every N] [--delay CENTISECONDS] \
+                     [--effects all|NAME,NAME...] [--overlay NAME]";
+
+/// everything the runner needs that would otherwise come from the window loop
+struct Config {
+    width: usize,
+    height: usize,
+    threads: usize,
+    ticks: u32,
+    seed: Option<u64>,
+    script: Option<PathBuf>,
+    stats_every: u32,
+    frame: Option<PathBuf>,
+    load: Option<PathBuf>,
+    save: Option<PathBuf>,
+    materials: Option<PathBuf>,
+    level: Option<PathBuf>,
+    legend: Option<PathBuf>,
+    frames: Option<PathBuf>,
+    record: Option<PathBuf>,
+    from: u32,
+    to: u32,
+    every: u32,
+    delay: u16,
+    effects: Vec<Effect>,
+    overlay: Option<Overlay>,
+}
+
+impl Config {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut config = Config {
+            width: 600,
+            height: 500,
+            threads: 20,
+            ticks: 100,
+            seed: None,
+            script: None,
+            stats_every: 0,
+            frame: None,
+            load: None,
+            save: None,
+            materials: None,
+            level: None,
+            legend: None,
+            frames: None,
+            record: None,
+            from: 0,
+            to: u32::MAX,
+            every: 1,
+            delay: 2,
+            effects: Vec::new(),
+            overlay: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name)).cloned();
+            match arg.as_str() {
+                "--script" => config.script = Some(PathBuf::from(value(arg)?)),
+                "--ticks" => config.ticks = parse_number(&value(arg)?)?,
+                "--seed" => config.seed = Some(parse_number(&value(arg)?)?),
+                "--threads" => config.threads = parse_number(&value(arg)?)?,
+                "--stats-every" => config.stats_every = parse_number(&value(arg)?)?,
+                "--frame" => config.frame = Some(PathBuf::from(value(arg)?)),
+                "--load" => config.load = Some(PathBuf::from(value(arg)?)),
+                "--save" => config.save = Some(PathBuf::from(value(arg)?)),
+                "--materials" => config.materials = Some(PathBuf::from(value(arg)?)),
+                "--level" => config.level = Some(PathBuf::from(value(arg)?)),
+                "--legend" => config.legend = Some(PathBuf::from(value(arg)?)),
+                "--frames" => config.frames = Some(PathBuf::from(value(arg)?)),
+                "--record" => config.record = Some(PathBuf::from(value(arg)?)),
+                "--from" => config.from = parse_number(&value(arg)?)?,
+                "--to" => config.to = parse_number(&value(arg)?)?,
+                "--every" => config.every = parse_number::<u32>(&value(arg)?)?.max(1),
+                "--delay" => config.delay = parse_number(&value(arg)?)?,
+                "--effects" => config.effects = parse_effects(&value(arg)?)?,
+                "--overlay" => {
+                    let name = value(arg)?;
+                    config.overlay = Some(Overlay::find(&name).ok_or_else(|| format!("unknown overlay '{}'", name))?);
+                }
+                "--size" => {
+                    config.width = parse_number(&value(arg)?)?;
+                    config.height = parse_number(&value(arg)?)?;
+                }
+                other => return Err(format!("unknown argument '{}'\n{}", other, USAGE)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// one line of a spawn script. scripts are plain text, one command per line,
+/// with `#` starting a comment:
+///
+/// ```text
+/// cluster sand 300 40 12   # species, x, y and optional brush size
+/// particle water 10 10
+/// fan 100 200 3 0 16       # x, y, wind speed x and y, optional brush size
+/// stroke line paint stone 10 150 300 150 4   # shape, mode, species, from x y,
+///                                            # to x y, optional brush size
+/// tick 50                  # run some ticks before the next command
+/// clear
+/// undo                     # every command above is one step, and so is clear
+/// redo
+/// ```
+enum Command {
+    Particle(ParticleType, usize, usize),
+    Cluster(ParticleType, usize, usize, Option<usize>),
+    Fan(usize, usize, f32, f32, Option<usize>),
+    /// a tool dragged in a straight line from one point to the other
+    Stroke(Tool, (usize, usize), (usize, usize), Option<usize>),
+    Tick(u32),
+    Clear,
+    Undo,
+    Redo,
+}
+
+impl Command {
+    fn parse_script(source: &str, materials: &Materials) -> Result<Vec<Command>, String> {
+        source
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.split('#').next().unwrap_or_default().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(number, line)| Self::parse(line, materials).map_err(|err| format!("line {}: {}", number, err)))
+            .collect()
+    }
+
+    fn parse(line: &str, materials: &Materials) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let species = |idx: usize| {
+            let name = words.get(idx).ok_or("missing species")?;
+            materials.find(name).ok_or(format!("unknown species '{}'", name))
+        };
+        let number = |idx: usize| {
+            let word = words.get(idx).ok_or("missing number")?;
+            parse_number(word)
+        };
+
+        match words[0] {
+            "particle" => Ok(Command::Particle(species(1)?, number(2)?, number(3)?)),
+            "cluster" => {
+                let size = words.get(4).map(|word| parse_number(word)).transpose()?;
+                Ok(Command::Cluster(species(1)?, number(2)?, number(3)?, size))
+            }
+            "fan" => {
+                let speed = |idx: usize| parse_number::<f32>(words.get(idx).ok_or("missing speed")?);
+                let size = words.get(5).map(|word| parse_number(word)).transpose()?;
+                Ok(Command::Fan(number(1)?, number(2)?, speed(3)?, speed(4)?, size))
+            }
+            "stroke" => {
+                let word = |idx: usize| words.get(idx).copied().ok_or("missing tool");
+                let shape = Shape::find(word(1)?).ok_or(format!("unknown shape '{}'", word(1)?))?;
+                let mode = Mode::find(word(2)?).ok_or(format!("unknown mode '{}'", word(2)?))?;
+                let size = words.get(8).map(|word| parse_number(word)).transpose()?;
+                let tool = Tool::build(species(3)?, shape, mode);
+                Ok(Command::Stroke(tool, (number(4)?, number(5)?), (number(6)?, number(7)?), size))
+            }
+            "tick" => Ok(Command::Tick(number(1)? as u32)),
+            "clear" => Ok(Command::Clear),
+            "undo" => Ok(Command::Undo),
+            "redo" => Ok(Command::Redo),
+            other => Err(format!("unknown command '{}'", other)),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let config = Config::parse(args)?;
+    let mut world = match config.seed {
+        Some(seed) => SandBox::build_seeded(config.width, config.height, seed),
+        None => SandBox::build(config.width, config.height),
+    };
+    world.thread_count = config.threads;
+    if let Some(path) = &config.materials {
+        world.load_materials(path).map_err(|err| describe(path, err))?;
+    }
+    let commands = match &config.script {
+        Some(path) => {
+            let source = std::fs::read_to_string(path).map_err(|err| describe(path, err))?;
+            Command::parse_script(&source, &world.materials)?
+        }
+        None => Vec::new(),
+    };
+    if let Some(path) = &config.load {
+        world.load(path).map_err(|err| describe(path, err))?;
+    }
+    if let Some(path) = &config.level {
+        let image = Image::read(path).map_err(|err| describe(path, err))?;
+        let legend = match &config.legend {
+            Some(path) => Some(Legend::read(path, &world.materials).map_err(|err| describe(path, err))?),
+            None => None,
+        };
+        world.import_level(&image, legend.as_ref());
+    }
+
+    if let Some(path) = &config.frames {
+        std::fs::create_dir_all(path).map_err(|err| describe(path, err))?;
+    }
+    let mut recorder = match &config.record {
+        Some(path) => {
+            let recorder = Recorder::build(path, world.width, world.height, config.delay, &world.materials);
+            Some(recorder.map_err(|err| describe(path, err))?)
+        }
+        None => None,
+    };
+
+    // the effects only touch what gets written out, never the simulation
+    let mut effects = PostProcess::build();
+    config.effects.iter().for_each(|&effect| effects.set(effect, true));
+    world.profile = config.overlay == Some(Overlay::Cost);
+
+    let time = Instant::now();
+    commands.iter().try_for_each(|command| {
+        // every painting command is its own undo step
+        world.begin_edit();
+        match *command {
+            Command::Particle(species, x, y) => world.add_particle(species, x, y),
+            Command::Cluster(species, x, y, size) => {
+                let previous = world.cluster_size;
+                world.cluster_size = size.unwrap_or(previous);
+                world.add_cluster(species, x, y);
+                world.cluster_size = previous;
+            }
+            Command::Fan(x, y, vx, vy, size) => {
+                let previous = world.cluster_size;
+                world.cluster_size = size.unwrap_or(previous);
+                world.add_fan(x, y, vx, vy);
+                world.cluster_size = previous;
+            }
+            Command::Stroke(mut tool, from, to, size) => {
+                let previous = world.cluster_size;
+                world.cluster_size = size.unwrap_or(previous);
+                tool.stroke(&mut world, from.0, from.1, true);
+                tool.stroke(&mut world, to.0, to.1, true);
+                tool.stroke(&mut world, to.0, to.1, false);
+                world.cluster_size = previous;
+            }
+            Command::Tick(ticks) => {
+                (0..ticks).try_for_each(|_| step(&mut world, &config, &mut recorder, &mut effects))?
+            }
+            Command::Clear => world.clear(),
+            Command::Undo => {
+                world.end_edit();
+                world.undo().map_err(|err| format!("undo: {}", err))?;
+            }
+            Command::Redo => {
+                world.end_edit();
+                world.redo().map_err(|err| format!("redo: {}", err))?;
+            }
+        }
+        world.end_edit();
+        Ok::<(), String>(())
+    })?;
+    (0..config.ticks).try_for_each(|_| step(&mut world, &config, &mut recorder, &mut effects))?;
+    let elapsed = time.elapsed().as_secs_f32();
+
+    println!("ticks: {} in {:.3}s ({:.1} tps)", world.tick, elapsed, world.tick as f32 / elapsed);
+    print_stats(&world);
+
+    if let Some(path) = &config.frame {
+        let (width, height) = (world.width, world.height);
+        let pixels = capture(&mut world, &config, &mut effects);
+        write_image(path, width, height, &pixels).map_err(|err| describe(path, err))?;
+        println!("wrote {}", path.display());
+    }
+    if let (Some(recorder), Some(path)) = (recorder, &config.record) {
+        let frames = recorder.frames;
+        recorder.finish().map_err(|err| describe(path, err))?;
+        println!("wrote {} ({} frames)", path.display(), frames);
+    }
+    if let Some(path) = &config.save {
+        world.save(path).map_err(|err| describe(path, err))?;
+        println!("wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn step(
+    world: &mut SandBox,
+    config: &Config,
+    recorder: &mut Option<Recorder>,
+    effects: &mut PostProcess,
+) -> Result<(), String> {
+    world.update_par();
+    if config.stats_every != 0 && world.tick.is_multiple_of(config.stats_every) {
+        print_stats(world);
+    }
+
+    let tick = world.tick;
+    let capturing = config.frames.is_some() || recorder.is_some();
+    if !capturing || tick < config.from || tick > config.to || !(tick - config.from).is_multiple_of(config.every) {
+        return Ok(());
+    }
+    let (width, height) = (world.width, world.height);
+    let pixels = capture(world, config, effects);
+    if let Some(dir) = &config.frames {
+        let path = dir.join(format!("frame_{:06}.png", tick));
+        write_image(&path, width, height, &pixels).map_err(|err| describe(&path, err))?;
+    }
+    if let (Some(recorder), Some(path)) = (recorder, &config.record) {
+        recorder.push(&pixels).map_err(|err| describe(path, err))?;
+    }
+    Ok(())
+}
+
+/// what gets written out, the overlay when there is one and otherwise the
+/// frame with the effects on top
+fn capture(world: &mut SandBox, config: &Config, effects: &mut PostProcess) -> Vec<u32> {
+    match config.overlay {
+        Some(overlay) => world.to_debug(overlay),
+        None => effects.apply(world).to_vec(),
+    }
+}
+
+fn print_stats(world: &SandBox) {
+    let census = world.census();
+    let counts: Vec<String> = world
+        .materials
+        .paintable()
+        .map(|(species, material)| format!("{}={}", material.name, census[species.index()]))
+        .collect();
+    println!("tick {}: {}", world.tick, counts.join(" "));
+}
+
+fn describe(path: &Path, err: std::io::Error) -> String {
+    format!("{}: {}", path.display(), err)
+}
+
+fn parse_number<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse().map_err(|_| format!("'{}' is not a valid number", word))
+}
+
+/// `all` or a comma separated list of effect names
+fn parse_effects(list: &str) -> Result<Vec<Effect>, String> {
+    if list == "all" {
+        return Ok(Effect::ALL.to_vec());
+    }
+    list.split(',')
+        .map(|name| Effect::find(name.trim()).ok_or_else(|| format!("unknown effect '{}'", name)))
+        .collect()
+}
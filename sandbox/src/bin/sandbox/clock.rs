@@ -0,0 +1,95 @@
+/// ticks run per frame at each speed setting
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+const NORMAL_SPEED: usize = 2;
+
+/// decides how many ticks every frame runs. slow speeds carry the leftover
+/// fraction over so a quarter speed runs one tick every fourth frame
+pub struct Clock {
+    pub paused: bool,
+    speed: usize,
+    owed: f32,
+    /// ticks asked for one at a time while paused
+    steps: usize,
+}
+
+impl Clock {
+    pub fn build() -> Self {
+        Clock { paused: false, speed: NORMAL_SPEED, owed: 0., steps: 0 }
+    }
+
+    pub fn speed(&self) -> f32 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.owed = 0.;
+    }
+
+    /// runs a single tick on the next frame, stepping while running pauses
+    /// first so the tick can actually be looked at
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.owed = 0.;
+        self.steps += 1;
+    }
+
+    /// how many ticks to run this frame
+    pub fn ticks(&mut self) -> usize {
+        if self.paused {
+            return std::mem::take(&mut self.steps);
+        }
+        self.owed += self.speed();
+        let ticks = self.owed.floor();
+        self.owed -= ticks;
+        ticks as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(speed: f32) -> Clock {
+        let mut clock = Clock::build();
+        (0..SPEEDS.len()).for_each(|_| clock.slower());
+        while clock.speed() < speed {
+            clock.faster();
+        }
+        clock
+    }
+
+    #[test]
+    fn quarter_speed_ticks_every_fourth_frame() {
+        let mut clock = at(0.25);
+        let ticks: Vec<usize> = (0..12).map(|_| clock.ticks()).collect();
+        assert!(ticks == [0, 0, 0, 1].repeat(3));
+    }
+
+    #[test]
+    fn top_speed_runs_eight_ticks_a_frame() {
+        let mut clock = at(8.);
+        assert!((0..10).all(|_| clock.ticks() == 8));
+        clock.faster();
+        assert!(clock.ticks() == 8);
+    }
+
+    #[test]
+    fn paused_clocks_only_run_the_steps_asked_for() {
+        let mut clock = Clock::build();
+        clock.step();
+        clock.step();
+        assert!(clock.paused);
+        assert!(clock.ticks() == 2 && clock.ticks() == 0);
+        clock.toggle_pause();
+        assert!(clock.ticks() == 1);
+    }
+}
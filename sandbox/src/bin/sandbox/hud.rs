@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::Duration;
+use std::time::Instant;
+
+use sandbox::ParticleType;
+use sandbox::SandBox;
+use sandbox::font::ADVANCE;
+use sandbox::font::LINE_HEIGHT;
+use sandbox::font::draw_text;
+use sandbox::font::text_width;
+use sandbox::tools::Shape;
+
+use crate::input::InputState;
+
+/// frames kept for the graph, one pixel column each
+const GRAPH_FRAMES: usize = 120;
+const GRAPH_HEIGHT: usize = 24;
+/// how many frames the fps readout averages so it doesn't flicker
+const AVERAGE_FRAMES: usize = 30;
+/// counting every cell is a walk over the whole grid, so the material counts
+/// are only taken a few times a second
+const CENSUS_EVERY: Duration = Duration::from_millis(250);
+const MARGIN: usize = 2;
+const PADDING: usize = 3;
+pub const TEXT: u32 = 0xffe8e8e8;
+const FAST: u32 = 0xff50c878;
+const SLOW: u32 = 0xffe0c040;
+const STALLED: u32 = 0xffe05040;
+
+/// text and a frame time graph drawn over the top left of the frame
+pub struct Hud {
+    /// seconds per frame that counts as keeping up
+    target: f32,
+    times: VecDeque<f32>,
+    lines: Vec<String>,
+    census: Vec<usize>,
+    counted: Option<Instant>,
+}
+
+impl Hud {
+    pub fn build(fps: usize) -> Self {
+        Hud { target: 1. / fps as f32, times: VecDeque::new(), lines: Vec::new(), census: Vec::new(), counted: None }
+    }
+
+    /// how long the last frame took from start to finish, in seconds
+    pub fn push_frame(&mut self, seconds: f32) {
+        if self.times.len() == GRAPH_FRAMES {
+            self.times.pop_front();
+        }
+        self.times.push_back(seconds);
+    }
+
+    /// reads everything the text shows out of the world, done before the frame
+    /// is drawn since that borrows the world until it's on screen
+    pub fn refresh(&mut self, world: &SandBox, input: &InputState) {
+        let recent = self.times.iter().rev().take(AVERAGE_FRAMES);
+        let average = recent.clone().sum::<f32>() / recent.count().max(1) as f32;
+        let clock = &input.clock;
+        let tool = &input.tool;
+        self.lines.clear();
+        self.lines.push(format!("fps {:.0}  {:.1}ms", 1. / average.max(f32::EPSILON), average * 1000.));
+        self.lines.push(format!(
+            "tick {}  speed {}x{}",
+            world.tick,
+            clock.speed(),
+            if clock.paused { "  paused" } else { "" }
+        ));
+        self.lines.push(format!("threads {}  chunk offset {}", world.thread_count, world.chunk_offset));
+        let shape = match tool.shape {
+            Shape::Spray(density) => format!("spray {:.3}", density),
+            shape => shape.name().to_string(),
+        };
+        self.lines.push(format!(
+            "{} {} {}  brush {}",
+            world.materials.get(tool.species).name,
+            shape,
+            tool.mode.name(),
+            world.cluster_size
+        ));
+
+        // a reload that added materials can't wait for the next count
+        let due = self.counted.is_none_or(|counted| counted.elapsed() >= CENSUS_EVERY);
+        if due || self.census.len() != world.materials.len() {
+            self.census = world.census();
+            self.counted = Some(Instant::now());
+        }
+        // biggest first, empty space isn't worth a line
+        let mut counts: Vec<(ParticleType, usize)> = world
+            .materials
+            .iter()
+            .map(|(species, _)| (species, self.census[species.index()]))
+            .filter(|&(species, count)| species != ParticleType::EMPTY && count > 0)
+            .collect();
+        counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        if !counts.is_empty() {
+            self.lines.push(String::new());
+        }
+        counts.into_iter().for_each(|(species, count)| {
+            self.lines.push(format!("{} {}", world.materials.get(species).name, count));
+        });
+
+        if let Some((x, y)) = input.inspect {
+            let particle = world.get(x, y);
+            self.lines.push(String::new());
+            self.lines.push(format!("cell {},{}  {}", x, y, world.materials.get(particle.species).name));
+            self.lines.push(format!(
+                "{}  {}  {}",
+                particle.behavior.map_or("none", |behavior| behavior.name()),
+                if particle.awake { "awake" } else { "asleep" },
+                if particle.direction_bias { "right" } else { "left" }
+            ));
+            self.lines.push(format!("velocity {:.2},{:.2}", particle.vx, particle.vy));
+            self.lines.push(format!("temperature {:.1}  color {:08x}", particle.temperature, particle.color));
+        }
+    }
+
+    /// draws the panel over whatever is on the screen
+    pub fn draw(&self, pixels: &mut [u32], width: usize) {
+        let height = pixels.len() / width.max(1);
+
+        let text = self.lines.iter().map(|line| text_width(line)).max().unwrap_or_default();
+        let inner = text.max(GRAPH_FRAMES);
+        let right = (MARGIN + PADDING * 2 + inner).min(width);
+        let bottom = (MARGIN + PADDING * 3 + GRAPH_HEIGHT + self.lines.len() * LINE_HEIGHT).min(height);
+        shade(pixels, width, MARGIN..right, MARGIN..bottom);
+
+        let (left, mut top) = (MARGIN + PADDING, MARGIN + PADDING);
+        self.lines.iter().for_each(|line| {
+            draw_text(pixels, width, left, top, line, TEXT);
+            top += LINE_HEIGHT;
+        });
+
+        // anything at or past three frames of budget fills the whole height
+        top += PADDING;
+        let bottom = top + GRAPH_HEIGHT;
+        let scale = GRAPH_HEIGHT as f32 / (self.target * 3.);
+        self.times.iter().enumerate().for_each(|(idx, &time)| {
+            let color = match time / self.target {
+                late if late <= 1.1 => FAST,
+                late if late <= 2. => SLOW,
+                _ => STALLED,
+            };
+            let bar = ((time * scale) as usize).clamp(1, GRAPH_HEIGHT);
+            (bottom - bar..bottom).filter(|&y| y < height && left + idx < width).for_each(|y| {
+                pixels[y * width + left + idx] = color;
+            });
+        });
+        // the target frame time as a dotted line across the graph
+        let target = bottom - (self.target * scale) as usize;
+        (0..GRAPH_FRAMES).step_by(ADVANCE).filter(|&x| target < height && left + x < width).for_each(|x| {
+            pixels[target * width + left + x] = TEXT;
+        });
+    }
+}
+
+/// halves the brightness under a panel so text stays readable on anything
+pub fn shade(pixels: &mut [u32], width: usize, columns: Range<usize>, rows: Range<usize>) {
+    rows.for_each(|y| {
+        pixels[y * width + columns.start..y * width + columns.end].iter_mut().for_each(|pixel| {
+            *pixel = 0xff000000 | (*pixel >> 1 & 0x7f7f7f);
+        });
+    });
+}
@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use minifb::Key;
+use minifb::KeyRepeat;
+use minifb::MouseButton;
+use minifb::MouseMode;
+use minifb::Window;
+use sandbox::ParticleType;
+use sandbox::SandBox;
+use sandbox::overlay::Overlay;
+use sandbox::post_process::Effect;
+use sandbox::post_process::PostProcess;
+use sandbox::recording::Recorder;
+use sandbox::tools::Mode;
+use sandbox::tools::Shape;
+use sandbox::tools::Tool;
+
+use crate::clock::Clock;
+use crate::palette::Palette;
+
+const SAVE_FILE: &str = "world.sbx";
+const RECORDING_FILE: &str = "recording.gif";
+/// air speed of a painted fan in particles per tick
+const FAN_SPEED: f32 = 3.;
+/// keys that pick what the tool paints with
+const MATERIAL_KEYS: [(Key, ParticleType); 14] = [
+    (Key::C, ParticleType::SAND),
+    (Key::W, ParticleType::WATER),
+    (Key::S, ParticleType::STONE),
+    (Key::Key3, ParticleType::SMOKE),
+    (Key::Key4, ParticleType::LAVA),
+    (Key::Key5, ParticleType::ICE),
+    (Key::Key6, ParticleType::ACID),
+    (Key::Key7, ParticleType::CHLORINE),
+    (Key::N, ParticleType::ANCHOR),
+    (Key::F, ParticleType::FIRE),
+    (Key::G, ParticleType::EMPTY),
+    (Key::A, ParticleType::GRAVEL),
+    (Key::D, ParticleType::WOOD),
+    (Key::O, ParticleType::OIL),
+];
+
+/// whatever the controls need to remember from one frame to the next
+pub struct InputState {
+    mouse: (f32, f32),
+    fan: (f32, f32),
+    /// shown instead of the particles when set
+    pub overlay: Option<Overlay>,
+    /// on the left mouse button
+    pub tool: Tool,
+    /// on the right mouse button
+    eraser: Tool,
+    pub clock: Clock,
+    pub hud: bool,
+    pub palette: bool,
+    /// the left button was down last frame
+    left: bool,
+    /// the left button went down on the palette, so it picks rather than
+    /// paints until it's let go
+    picking: bool,
+    /// the cell the hud shows the fields of
+    pub inspect: Option<(usize, usize)>,
+}
+
+impl InputState {
+    pub fn build() -> Self {
+        InputState {
+            mouse: (0., 0.),
+            fan: (FAN_SPEED, 0.),
+            overlay: None,
+            tool: Tool::build(ParticleType::SAND, Shape::Circle, Mode::Paint),
+            eraser: Tool::build(ParticleType::EMPTY, Shape::Circle, Mode::Erase),
+            clock: Clock::build(),
+            hud: true,
+            palette: true,
+            left: false,
+            picking: false,
+            inspect: None,
+        }
+    }
+}
+
+pub fn get_inputs(
+    window: &mut Window,
+    world: &mut SandBox,
+    recorder: &mut Option<Recorder>,
+    state: &mut InputState,
+    effects: &mut PostProcess,
+    palette: &Palette,
+) {
+    let (mx, my) = window.get_mouse_pos(MouseMode::Clamp).unwrap();
+    let (dx, dy) = (mx - state.mouse.0, my - state.mouse.1);
+    state.mouse = (mx, my);
+    let ctrl = window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl);
+    if ctrl && window.is_key_pressed(Key::Z, KeyRepeat::Yes) {
+        report_history("undo", world.undo());
+    }
+    if ctrl && window.is_key_pressed(Key::Y, KeyRepeat::Yes) {
+        report_history("redo", world.redo());
+    }
+    // everything painted while a button or the fan keys are held is one undo
+    // step. it's closed at the bottom once they're all let go, after a line
+    // or rectangle has had its chance to go down
+    let (left, right) = (window.get_mouse_down(MouseButton::Left), window.get_mouse_down(MouseButton::Right));
+    if left && !state.left && state.palette && palette.contains(my as usize) {
+        state.picking = true;
+        if let Some(species) = palette.hit(mx as usize, my as usize) {
+            state.tool.species = species;
+        }
+    }
+    state.picking &= left;
+    state.left = left;
+    let left = left && !state.picking;
+    let editing = left || right || window.is_key_down(Key::B) || window.is_key_down(Key::X);
+    if editing {
+        world.begin_edit();
+    }
+    state.tool.stroke(world, mx as usize, my as usize, left);
+    state.eraser.stroke(world, mx as usize, my as usize, right);
+    // holding it follows the mouse, letting go keeps watching that cell
+    if window.is_key_down(Key::I) {
+        state.inspect = Some((mx as usize, my as usize));
+        state.hud = true;
+    }
+    if window.is_key_pressed(Key::H, KeyRepeat::No) {
+        state.hud = !state.hud;
+    }
+    if window.is_key_pressed(Key::M, KeyRepeat::No) {
+        state.palette = !state.palette;
+    }
+    MATERIAL_KEYS.iter().filter(|&&(key, _)| window.is_key_pressed(key, KeyRepeat::No)).for_each(|&(_, species)| {
+        state.tool.species = species;
+        println!("material: {}", world.materials.get(species).name);
+    });
+    if window.is_key_pressed(Key::T, KeyRepeat::No) {
+        state.tool.shape = state.tool.shape.next();
+        println!("shape: {}", state.tool.shape.name());
+    }
+    if window.is_key_pressed(Key::E, KeyRepeat::No) {
+        state.tool.mode = state.tool.mode.next();
+        println!("mode: {}", state.tool.mode.name());
+    }
+    if let Shape::Spray(density) = &mut state.tool.shape {
+        if window.is_key_pressed(Key::Key9, KeyRepeat::Yes) {
+            *density = (*density / 1.5).max(0.005);
+            println!("spray density: {:.3}", density);
+        }
+        if window.is_key_pressed(Key::Key0, KeyRepeat::Yes) {
+            *density = (*density * 1.5).min(1.);
+            println!("spray density: {:.3}", density);
+        }
+    }
+    if window.is_key_down(Key::B) {
+        // fans blow the way the mouse is dragged, holding still keeps the
+        // last direction
+        let length = (dx * dx + dy * dy).sqrt();
+        if length > 0.5 {
+            state.fan = (dx / length * FAN_SPEED, dy / length * FAN_SPEED);
+        }
+        world.add_fan(mx as usize, my as usize, state.fan.0, state.fan.1);
+    }
+    if window.is_key_down(Key::X) {
+        world.remove_fans(mx as usize, my as usize);
+    }
+    if window.is_key_pressed(Key::R, KeyRepeat::No) {
+        world.clear();
+    }
+    if window.is_key_pressed(Key::K, KeyRepeat::No) {
+        match world.save(Path::new(SAVE_FILE)) {
+            Ok(()) => println!("saved world to {}", SAVE_FILE),
+            Err(err) => println!("failed to save {}: {}", SAVE_FILE, err),
+        }
+    }
+    if window.is_key_pressed(Key::L, KeyRepeat::No) {
+        match world.load(Path::new(SAVE_FILE)) {
+            Ok(()) => println!("loaded world from {}", SAVE_FILE),
+            Err(err) => println!("failed to load {}: {}", SAVE_FILE, err),
+        }
+    }
+    if window.is_key_pressed(Key::V, KeyRepeat::No) {
+        match recorder.take() {
+            Some(active) => stop_recording(active),
+            None => match Recorder::build(Path::new(RECORDING_FILE), world.width, world.height, 2, &world.materials) {
+                Ok(started) => {
+                    println!("recording to {}", RECORDING_FILE);
+                    *recorder = Some(started);
+                }
+                Err(err) => println!("failed to start recording {}: {}", RECORDING_FILE, err),
+            },
+        }
+    }
+    if window.is_key_pressed(Key::P, KeyRepeat::No) {
+        state.overlay = match state.overlay {
+            Some(overlay) => overlay.next(),
+            None => Some(Overlay::ALL[0]),
+        };
+        // the clock is only read per tile while someone's looking at it
+        world.profile = state.overlay == Some(Overlay::Cost);
+        println!("overlay: {}", state.overlay.map_or("off", |overlay| overlay.name()));
+    }
+    // f1 through f4 flip each post process effect in the order they're applied
+    [Key::F1, Key::F2, Key::F3, Key::F4].into_iter().zip(Effect::ALL).for_each(|(key, effect)| {
+        if window.is_key_pressed(key, KeyRepeat::No) {
+            let status = if effects.toggle(effect) { "on" } else { "off" };
+            println!("{} {}", effect.name(), status);
+        }
+    });
+    if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+        state.clock.toggle_pause();
+        println!("{}", if state.clock.paused { "paused" } else { "running" });
+    }
+    // holding the step key keeps stepping at the key repeat rate
+    if window.is_key_pressed(Key::Period, KeyRepeat::Yes) {
+        state.clock.step();
+    }
+    if window.is_key_pressed(Key::LeftBracket, KeyRepeat::No) {
+        state.clock.slower();
+        println!("speed: {}x", state.clock.speed());
+    }
+    if window.is_key_pressed(Key::RightBracket, KeyRepeat::No) {
+        state.clock.faster();
+        println!("speed: {}x", state.clock.speed());
+    }
+    if window.is_key_pressed(Key::Equal, KeyRepeat::No) {
+        world.thread_count += 1;
+        println!("threads: {}", world.thread_count);
+    }
+    if window.is_key_pressed(Key::Minus, KeyRepeat::No) {
+        world.thread_count = world.thread_count.saturating_sub(1).max(1);
+        println!("threads: {}", world.thread_count);
+    }
+    if window.is_key_down(Key::Key1) {
+        world.cluster_size = world.cluster_size.saturating_sub(1);
+    }
+    if window.is_key_down(Key::Key2) {
+        world.cluster_size += 1;
+    }
+    if !editing {
+        world.end_edit();
+    }
+}
+
+fn report_history(action: &str, result: std::io::Result<bool>) {
+    match result {
+        Ok(true) => println!("{}", action),
+        Ok(false) => println!("nothing to {}", action),
+        Err(err) => println!("failed to {}: {}", action, err),
+    }
+}
+
+pub fn stop_recording(recorder: Recorder) {
+    let frames = recorder.frames;
+    match recorder.finish() {
+        Ok(()) => println!("saved {} frames to {}", frames, RECORDING_FILE),
+        Err(err) => println!("failed to save {}: {}", RECORDING_FILE, err),
+    }
+}
@@ -0,0 +1,134 @@
+mod clock;
+mod hud;
+mod input;
+mod palette;
+
+use std::path::Path;
+
+use hud::Hud;
+use input::InputState;
+use input::get_inputs;
+use input::stop_recording;
+use minifb::Scale;
+use minifb::Window;
+use minifb::WindowOptions;
+use palette::Palette;
+use sandbox::SandBox;
+use sandbox::image::Image;
+use sandbox::level::Legend;
+use sandbox::materials::FileWatcher;
+use sandbox::post_process::PostProcess;
+
+const WIDTH: usize = 600;
+const HEIGHT: usize = 500;
+const FPS: usize = 100;
+const MATERIALS_FILE: &str = "materials.toml";
+
+fn main() {
+    unsafe {
+        std::env::set_var("RUST_BACKTRACE", "full");
+    }
+
+    let mut window = Window::new(
+        "falling sand sandbox game",
+        WIDTH,
+        HEIGHT,
+        WindowOptions { scale: Scale::X2, ..Default::default() },
+    )
+    .expect("failed to grab window handle");
+    window.set_target_fps(FPS);
+
+    let mut world = SandBox::build(WIDTH, HEIGHT);
+
+    // `--level image.png [--legend legend.txt]` starts from an authored level,
+    // `--materials file.toml` picks which materials file gets watched
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let arg = |name: &str| args.iter().position(|arg| arg == name).and_then(|idx| args.get(idx + 1));
+    let mut materials = FileWatcher::build(Path::new(arg("--materials").map_or(MATERIALS_FILE, |path| path)));
+    if materials.path.exists() {
+        reload_materials(&mut world, &materials.path);
+    }
+    if let Some(level) = arg("--level") {
+        let image = Image::read(Path::new(level)).expect("failed to read level image");
+        let legend = arg("--legend")
+            .map(|legend| Legend::read(Path::new(legend), &world.materials).expect("failed to read legend"));
+        world.import_level(&image, legend.as_ref());
+    }
+
+    let mut recorder = None;
+    let mut input = InputState::build();
+    let mut effects = PostProcess::build();
+    effects.set_all(true);
+    let mut hud = Hud::build(FPS);
+    let mut palette = Palette::build();
+    // the frame with the hud and palette on top, the frame itself is left
+    // alone so recordings come out clean
+    let mut screen = Vec::new();
+    let mut frame: u64 = 0;
+    while window.is_open() {
+        let time = std::time::Instant::now();
+
+        if materials.changed() {
+            reload_materials(&mut world, &materials.path);
+        }
+        palette.layout(&world);
+        get_inputs(&mut window, &mut world, &mut recorder, &mut input, &mut effects, &palette);
+        // painting and inspecting still go through while paused, only the
+        // ticks stop
+        (0..input.clock.ticks()).for_each(|_| world.update_par());
+        if input.hud {
+            hud.refresh(&world, &input);
+        }
+
+        let (width, height) = (world.width, world.height);
+        let debug;
+        let pixels = match input.overlay {
+            Some(overlay) => {
+                debug = world.to_debug(overlay);
+                &debug
+            }
+            None => effects.apply(&mut world),
+        };
+        // gif delays are in hundredths of a second and players clamp anything
+        // under two, so at 100 fps only every other frame is kept. counting
+        // frames rather than ticks keeps the recording at the speed it was
+        // watched at, pauses included
+        if let Some(active) = &mut recorder
+            && frame.is_multiple_of(2)
+            && let Err(err) = active.push(pixels)
+        {
+            // something like a full disk ends the recording, not the game
+            println!("stopped recording, failed to write a frame: {}", err);
+            recorder = None;
+        }
+        let pixels = if input.hud || input.palette {
+            screen.clear();
+            screen.extend_from_slice(pixels);
+            if input.hud {
+                hud.draw(&mut screen, width);
+            }
+            if input.palette {
+                palette.draw(&mut screen, &world, input.tool.species);
+            }
+            &screen
+        }
+        else {
+            pixels
+        };
+        window.update_with_buffer(pixels, width, height).expect("failed to update window");
+        frame += 1;
+        hud.push_frame(time.elapsed().as_secs_f32());
+    }
+    if let Some(recorder) = recorder {
+        stop_recording(recorder);
+    }
+}
+
+/// a broken file is reported and the old materials stay, so a half typed edit
+/// doesn't take the game down
+fn reload_materials(world: &mut SandBox, path: &Path) {
+    match world.load_materials(path) {
+        Ok(()) => println!("loaded materials from {}", path.display()),
+        Err(err) => println!("failed to load {}: {}", path.display(), err),
+    }
+}
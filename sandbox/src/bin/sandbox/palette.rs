@@ -0,0 +1,96 @@
+use sandbox::ParticleType;
+use sandbox::SandBox;
+use sandbox::font::GLYPH_HEIGHT;
+use sandbox::font::draw_text;
+use sandbox::font::text_width;
+
+use crate::hud::TEXT;
+use crate::hud::shade;
+
+/// side of the colored square in front of every name
+const SWATCH: usize = 7;
+const PADDING: usize = 3;
+/// space between one entry and the next
+const GAP: usize = 8;
+const ROW_HEIGHT: usize = SWATCH + PADDING * 2;
+const DIM: u32 = 0xff909090;
+const OUTLINE: u32 = 0xff202020;
+
+/// one material's spot on the strip
+struct Entry {
+    species: ParticleType,
+    x: usize,
+    y: usize,
+    width: usize,
+}
+
+/// a strip along the bottom of the frame with every paintable material on it,
+/// clicking one picks it for the brush. it's laid out again from the material
+/// list every frame so reloaded or added materials show up on their own
+pub struct Palette {
+    entries: Vec<Entry>,
+    /// first row of the strip
+    top: usize,
+}
+
+impl Palette {
+    pub fn build() -> Self {
+        Palette { entries: Vec::new(), top: 0 }
+    }
+
+    /// fills the rows left to right, starting a new one whenever the next name
+    /// wouldn't fit. the rows are stacked up from the bottom edge
+    pub fn layout(&mut self, world: &SandBox) {
+        self.entries.clear();
+        let (mut x, mut row) = (PADDING, 0);
+        world.materials.paintable().for_each(|(species, material)| {
+            let width = SWATCH + PADDING + text_width(&material.name);
+            if x > PADDING && x + width + PADDING > world.width {
+                (x, row) = (PADDING, row + 1);
+            }
+            self.entries.push(Entry { species, x, y: row * ROW_HEIGHT + PADDING, width });
+            x += width + GAP;
+        });
+        let height = (row + 1) * ROW_HEIGHT;
+        self.top = world.height.saturating_sub(height);
+        self.entries.iter_mut().for_each(|entry| entry.y += self.top);
+    }
+
+    /// whether the cell is covered by the strip at all
+    pub fn contains(&self, y: usize) -> bool {
+        !self.entries.is_empty() && y >= self.top
+    }
+
+    /// the material whose swatch or name is under the cell
+    pub fn hit(&self, x: usize, y: usize) -> Option<ParticleType> {
+        self.entries
+            .iter()
+            .find(|entry| {
+                let rows = entry.y - PADDING..entry.y + SWATCH + PADDING;
+                (entry.x..entry.x + entry.width).contains(&x) && rows.contains(&y)
+            })
+            .map(|entry| entry.species)
+    }
+
+    /// draws the strip over whatever is on the screen, the selected material
+    /// gets a bright outline and name
+    pub fn draw(&self, pixels: &mut [u32], world: &SandBox, selected: ParticleType) {
+        let width = world.width;
+        let height = pixels.len() / width.max(1);
+        shade(pixels, width, 0..width, self.top.min(height)..height);
+        self.entries.iter().filter(|entry| entry.y + SWATCH <= height).for_each(|entry| {
+            let material = world.materials.get(entry.species);
+            let color = material.color.map_or(0xff000000, |spec| spec.base());
+            let outline = if entry.species == selected { TEXT } else { OUTLINE };
+            (0..SWATCH).for_each(|dy| {
+                (0..SWATCH).filter(|dx| entry.x + dx < width).for_each(|dx| {
+                    let edge = dx == 0 || dy == 0 || dx == SWATCH - 1 || dy == SWATCH - 1;
+                    pixels[(entry.y + dy) * width + entry.x + dx] = if edge { outline } else { color };
+                });
+            });
+            let name = if entry.species == selected { TEXT } else { DIM };
+            let y = entry.y + (SWATCH - GLYPH_HEIGHT) / 2;
+            draw_text(pixels, width, entry.x + SWATCH + PADDING, y, &material.name, name);
+        });
+    }
+}
@@ -1,6 +1,11 @@
-use rand::random_range;
+use crate::pcg::Pcg;
 
-#[derive(Clone, Copy)]
+/// side of a chunk. it stays the same however many threads there are, since
+/// the chunk layout decides the order cells update in and with it the world a
+/// seed produces
+pub const CHUNK_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
 pub struct Chunk {
     pub xmin: usize,
     pub xmax: usize,
@@ -13,19 +18,69 @@ impl Chunk {
         Chunk { xmin, xmax, ymin, ymax }
     }
 
-    pub fn columnar_chunks(height: usize, width: usize, count: usize, offset: i32) -> Vec<Chunk> {
-        let chunk_size = width / count;
-        let offset = random_range(0..offset) as usize;
-
-        let mut xmin = 0;
-        let mut xmax = chunk_size - offset;
-        let mut chunks = Vec::new();
-        while xmin < width {
-            chunks.push(Self::build(xmin, xmax, 0, height));
-            xmin = xmax;
-            xmax = (xmax + chunk_size).min(width);
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.xmin && x < self.xmax && y >= self.ymin && y < self.ymax
+    }
+
+    /// the smallest chunk covering both
+    pub fn union(&self, other: Chunk) -> Chunk {
+        Self::build(
+            self.xmin.min(other.xmin),
+            self.xmax.max(other.xmax),
+            self.ymin.min(other.ymin),
+            self.ymax.max(other.ymax),
+        )
+    }
+
+    /// grows the chunk by the halo on every side, clipped to the grid. this is
+    /// the full region a thread processing this chunk is allowed to touch
+    pub fn expand(&self, halo: usize, width: usize, height: usize) -> Chunk {
+        Self::build(
+            self.xmin.saturating_sub(halo),
+            (self.xmax + halo).min(width),
+            self.ymin.saturating_sub(halo),
+            (self.ymax + halo).min(height),
+        )
+    }
+
+    /// splits the grid into square chunks and colors them like a checkerboard
+    /// with four colors, one per phase. two chunks in the same phase always
+    /// have a whole chunk of another phase between them, so as long as the
+    /// chunk size is at least twice the halo no two threads in a phase can
+    /// ever reach the same cell. the grid origin is jittered every call so the
+    /// chunk borders don't leave visible seams
+    pub fn checkerboard_chunks(
+        height: usize,
+        width: usize,
+        size: usize,
+        halo: usize,
+        offset: i32,
+        rng: &mut Pcg,
+    ) -> [Vec<Chunk>; 4] {
+        let size = size.max(2 * halo).max(1);
+        let jitter = (offset as usize).clamp(1, size);
+        let xoffset = rng.random_range(0..jitter);
+        let yoffset = rng.random_range(0..jitter);
+
+        let mut phases: [Vec<Chunk>; 4] = Default::default();
+        let mut ymin = 0;
+        let mut ymax = (size - yoffset).min(height);
+        let mut row = 0;
+        while ymin < height {
+            let mut xmin = 0;
+            let mut xmax = (size - xoffset).min(width);
+            let mut col = 0;
+            while xmin < width {
+                phases[(row % 2) * 2 + col % 2].push(Self::build(xmin, xmax, ymin, ymax));
+                xmin = xmax;
+                xmax = (xmax + size).min(width);
+                col += 1;
+            }
+            ymin = ymax;
+            ymax = (ymax + size).min(height);
+            row += 1;
         }
 
-        chunks
+        phases
     }
 }
@@ -0,0 +1,108 @@
+use crate::particle_updates::Behavior;
+use crate::particle_updates::Burning;
+use crate::particles::Particle;
+use crate::sandbox::Handler;
+
+impl Handler<'_> {
+    /// anything flammable that got hot enough starts burning where it is
+    pub fn ignite(&mut self) {
+        let params = self.get_params_here();
+        if params.ignition_temperature <= 0.
+            || self.here.temperature < params.ignition_temperature
+            || self.here.is_burning()
+        {
+            return;
+        }
+        self.get_mut_here().behavior = Some(Behavior::Burning(Burning));
+        self.reup_here();
+    }
+
+    /// lights up a neighbour straight away if it can burn at all
+    pub fn set_alight(&mut self, dx: isize, dy: isize) {
+        let params = self.get_params(dx, dy);
+        if params.ignition_temperature <= 0. || self.get(dx, dy).is_burning() {
+            return;
+        }
+        let neighbour = self.get_mut_unchecked(dx, dy);
+        neighbour.behavior = Some(Behavior::Burning(Burning));
+        neighbour.temperature = neighbour.temperature.max(params.ignition_temperature);
+    }
+
+    /// the burning cell and the water next to it settle at a shared
+    /// temperature straight away. that's usually enough to boil the water
+    /// and leave the fuel below its ignition point, but a hot enough fire
+    /// just catches again
+    pub fn extinguish(&mut self, dx: isize, dy: isize) {
+        let mut sandbox = self.sandbox;
+        let materials = &sandbox.deref().materials;
+        let material = materials.get(self.here.species);
+        let water = self.get(dx, dy);
+        let here_capacity = material.params.heat_capacity.max(f32::EPSILON);
+        let water_capacity = self.get_params(dx, dy).heat_capacity.max(f32::EPSILON);
+        let temperature = (self.here.temperature * here_capacity + water.temperature * water_capacity)
+            / (here_capacity + water_capacity);
+        self.get_mut_unchecked(dx, dy).temperature = temperature;
+
+        // a flame on its own has nothing left once it's out
+        if material.behavior.is_some_and(|behavior| behavior == Behavior::Burning(Burning)) {
+            self.burn_out();
+            self.get_mut_here().temperature = temperature;
+            return;
+        }
+        let time = self.sandbox.deref().color_shift;
+        let color = material.color(time, self.rng);
+        let here = self.get_mut_here();
+        here.behavior = material.behavior;
+        here.color = color;
+        here.temperature = temperature;
+        self.reup_here();
+    }
+
+    /// replaces the cell with whatever its material leaves behind, still as
+    /// hot as the fire was
+    pub fn burn_out(&mut self) {
+        let time = self.sandbox.deref().color_shift;
+        let mut sandbox = self.sandbox;
+        let materials = &sandbox.deref().materials;
+        let into = materials.get(self.here.species).burns_into;
+        let mut remains = Particle::build_color(into, time, materials, self.rng);
+        remains.temperature = self.here.temperature;
+        *self.get_mut_here() = remains;
+        self.reup_here();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particle_updates::Behavior;
+    use crate::particle_updates::Burning;
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    fn light(world: &mut SandBox, name: &str, x: usize, y: usize) -> ParticleType {
+        let species = world.materials.find(name).expect("missing built in material");
+        world.put_particle(species, x, y);
+        let particle = world.get_mut(x, y).expect("cell is in bounds");
+        particle.behavior = Some(Behavior::Burning(Burning));
+        particle.temperature = 400.;
+        species
+    }
+
+    #[test]
+    fn burning_sawdust_still_falls() {
+        let mut world = SandBox::build_seeded(32, 40, 5);
+        let sawdust = light(&mut world, "sawdust", 16, 5);
+        (0..8).for_each(|_| world.update());
+        let lowest = (0..world.height).rev().find(|&y| (0..world.width).any(|x| world.get(x, y).species == sawdust));
+        assert!(lowest.is_some_and(|y| y > 8), "burning sawdust stayed up at {:?}", lowest);
+    }
+
+    #[test]
+    fn burning_oil_still_flows() {
+        let mut world = SandBox::build_seeded(40, 20, 5);
+        let oil = (10..20).map(|y| light(&mut world, "oil", 20, y)).last().expect("placed some oil");
+        (0..15).for_each(|_| world.update());
+        let columns = (0..world.width).filter(|&x| (0..world.height).any(|y| world.get(x, y).species == oil)).count();
+        assert!(columns > 3, "burning oil only covers {} columns", columns);
+    }
+}
@@ -0,0 +1,48 @@
+/// width and height of every glyph in pixels
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+/// distance from one character to the next and one line to the next
+pub const ADVANCE: usize = GLYPH_WIDTH + 1;
+pub const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+/// a tiny 3 by 5 font, each glyph packed row by row from the top left into
+/// the low 15 bits. upper case letters draw as lower case and anything missing
+/// draws as a question mark
+const GLYPHS: [(char, u16); 52] = [
+    ('0', 0x7b6f), ('1', 0x2c97), ('2', 0x73e7), ('3', 0x72cf), ('4', 0x5bc9), ('5', 0x79cf), ('6', 0x79ef),
+    ('7', 0x7252), ('8', 0x7bef), ('9', 0x7bcf), ('a', 0x2bed), ('b', 0x6bae), ('c', 0x3923), ('d', 0x6b6e),
+    ('e', 0x79a7), ('f', 0x79a4), ('g', 0x396b), ('h', 0x5bed), ('i', 0x7497), ('j', 0x126a), ('k', 0x5bad),
+    ('l', 0x4927), ('m', 0x5fed), ('n', 0x6b6d), ('o', 0x2b6a), ('p', 0x6ba4), ('q', 0x2b73), ('r', 0x6bad),
+    ('s', 0x388e), ('t', 0x7492), ('u', 0x5b6f), ('v', 0x5b52), ('w', 0x5bfd), ('x', 0x5aad), ('y', 0x5a92),
+    ('z', 0x72a7), (' ', 0x0000), ('.', 0x0002), (':', 0x0410), ('-', 0x01c0), ('/', 0x12a4), ('%', 0x52a5),
+    ('(', 0x2922), (')', 0x224a), (',', 0x0014), ('=', 0x0e38), ('_', 0x0007), ('+', 0x05d0), ('?', 0x7282),
+    ('[', 0x6926), (']', 0x324b), ('!', 0x2482),
+];
+
+fn glyph(ch: char) -> u16 {
+    let find = |ch: char| GLYPHS.iter().find(|&&(glyph, _)| glyph == ch).map(|&(_, bits)| bits);
+    find(ch.to_ascii_lowercase()).or_else(|| find('?')).unwrap_or_default()
+}
+
+/// how many pixels wide the text comes out
+pub fn text_width(text: &str) -> usize {
+    (text.chars().count() * ADVANCE).saturating_sub(1)
+}
+
+/// draws the text with its top left corner at x, y into a frame of the given
+/// width. whatever falls off the frame is cut off
+pub fn draw_text(pixels: &mut [u32], width: usize, x: usize, y: usize, text: &str, color: u32) {
+    let height = pixels.len() / width.max(1);
+    text.chars().enumerate().for_each(|(idx, ch)| {
+        let bits = glyph(ch);
+        (0..GLYPH_HEIGHT).for_each(|row| {
+            (0..GLYPH_WIDTH).for_each(|col| {
+                let (px, py) = (x + idx * ADVANCE + col, y + row);
+                let lit = bits >> (GLYPH_WIDTH * GLYPH_HEIGHT - 1 - (row * GLYPH_WIDTH + col)) & 1 == 1;
+                if lit && px < width && py < height {
+                    pixels[py * width + px] = color;
+                }
+            });
+        });
+    });
+}
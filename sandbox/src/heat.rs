@@ -0,0 +1,144 @@
+use crate::materials::AMBIENT_TEMPERATURE;
+use crate::sandbox::Handler;
+
+/// the most a cell may pass to one neighbour per tick. every cell trades with
+/// up to four neighbours, so anything above a quarter could overshoot and
+/// start oscillating
+const MAX_EXCHANGE: f32 = 0.25;
+
+/// changes smaller than this don't keep a tile awake, otherwise the last
+/// fraction of a degree would keep whole regions ticking forever
+const SETTLED: f32 = 0.05;
+
+impl Handler<'_> {
+    /// trades heat with the cell to the right and the one below, so a pair of
+    /// neighbours is handled by whichever of the two is visited. a particle
+    /// that moves into a chunk of a later phase gets visited again and trades
+    /// twice that tick, which only speeds it up a little since every trade
+    /// keeps the total the same. both cells are always inside this handler's
+    /// region, so this runs inside the chunked update without any extra
+    /// synchronization
+    pub fn conduct(&mut self) {
+        let params = self.get_params_here();
+        let mut temperature = self.here.temperature;
+        let mut active = false;
+
+        [(1, 0), (0, 1)].iter().for_each(|&(dx, dy)| {
+            let other_params = self.get_params(dx as isize, dy as isize);
+            let conductivity = params.conductivity.min(other_params.conductivity) * MAX_EXCHANGE;
+            if conductivity <= 0. {
+                return;
+            }
+            let (nx, ny) = (self.x + dx, self.y + dy);
+            if !self.region.contains(nx, ny) {
+                return;
+            }
+            // deliberately not waking anything here, only an exchange that
+            // actually moved some heat keeps the area going
+            let other = self.sandbox.deref().get_mut_unchecked(nx, ny);
+            // split so the total heat stays the same, the cell that holds less
+            // of it changes more
+            let here_capacity = params.heat_capacity.max(f32::EPSILON);
+            let other_capacity = other_params.heat_capacity.max(f32::EPSILON);
+            let flow = (other.temperature - temperature) * conductivity;
+            let here_change = flow * other_capacity / (here_capacity + other_capacity);
+            let other_change = flow * here_capacity / (here_capacity + other_capacity);
+            temperature += here_change;
+            other.temperature -= other_change;
+            active |= here_change.abs().max(other_change.abs()) > SETTLED;
+        });
+
+        let loss = (temperature - AMBIENT_TEMPERATURE) * params.cooling.clamp(0., 1.);
+        temperature -= loss;
+        active |= loss.abs() > SETTLED;
+
+        if temperature != self.here.temperature {
+            let here = self.sandbox.deref().get_mut_unchecked(self.x, self.y);
+            here.temperature = temperature;
+            self.here.temperature = temperature;
+        }
+        if active {
+            self.keep_active();
+        }
+    }
+
+    /// swaps the particle for whatever its material turns into once it gets
+    /// too hot or too cold. the temperature carries over
+    pub fn change_phase(&mut self) {
+        let mut sandbox = self.sandbox;
+        let materials = &sandbox.deref().materials;
+        let material = materials.get(self.here.species);
+        let phase = match (material.above, material.below) {
+            (Some(above), _) if self.here.temperature > above.temperature => above,
+            (_, Some(below)) if self.here.temperature < below.temperature => below,
+            _ => return,
+        };
+
+        let time = self.sandbox.deref().color_shift;
+        let into = materials.get(phase.into);
+        let color = into.color(time, self.rng);
+        let here = self.get_mut_here();
+        here.species = phase.into;
+        here.behavior = into.behavior;
+        here.color = color;
+        here.awake = true;
+        self.reup_here();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    fn heated(world: &mut SandBox, species: ParticleType, x: usize, y: usize, temperature: f32) {
+        world.put_particle(species, x, y);
+        let idx = y * world.width + x;
+        world.grid[idx].temperature = temperature;
+    }
+
+    /// stone next to an anchor with nothing else around, neither cools off
+    /// into the air
+    fn pair() -> SandBox {
+        let mut world = SandBox::build_seeded(2, 1, 1);
+        heated(&mut world, ParticleType::STONE, 0, 0, 500.);
+        heated(&mut world, ParticleType::ANCHOR, 1, 0, 20.);
+        world
+    }
+
+    #[test]
+    fn heat_flows_to_the_colder_neighbour() {
+        let mut world = pair();
+        // the first tick only picks up what was woken
+        (0..2).for_each(|_| world.update());
+        assert!(world.get(0, 0).temperature < 500. && world.get(1, 0).temperature > 20.);
+        (0..500).for_each(|_| world.update());
+        assert!((world.get(0, 0).temperature - world.get(1, 0).temperature).abs() < 1.);
+    }
+
+    #[test]
+    fn trading_heat_keeps_the_total() {
+        let mut world = pair();
+        let capacity = |world: &SandBox, species| world.materials.get(species).params.heat_capacity;
+        let total = |world: &SandBox| {
+            world.get(0, 0).temperature * capacity(world, ParticleType::STONE)
+                + world.get(1, 0).temperature * capacity(world, ParticleType::ANCHOR)
+        };
+        let start = total(&world);
+        (0..50).for_each(|_| {
+            world.update();
+            assert!((total(&world) - start).abs() < 0.01);
+        });
+    }
+
+    #[test]
+    fn water_boils_and_freezes() {
+        let mut world = SandBox::build_seeded(20, 10, 1);
+        heated(&mut world, ParticleType::WATER, 5, 9, 150.);
+        heated(&mut world, ParticleType::WATER, 15, 9, -30.);
+        (0..2).for_each(|_| world.update());
+        assert!(world.census()[ParticleType::STEAM.index()] == 1);
+        assert!(world.get(15, 9).species == ParticleType::ICE);
+        assert!(world.census()[ParticleType::WATER.index()] == 0);
+    }
+}
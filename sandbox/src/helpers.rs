@@ -1,13 +1,4 @@
-use std::time::Duration;
-
-use minifb::Key;
-use minifb::MouseButton;
-use minifb::MouseMode;
-use minifb::Window;
-use rand::random_range;
-
-use crate::particles::ParticleType;
-use crate::sandbox::SandBox;
+use crate::pcg::Pcg;
 
 pub struct LineTracer {
     x0: isize,
@@ -71,70 +62,20 @@ impl LineTracer {
     }
 }
 
-pub fn get_inputs(window: &mut Window, world: &mut SandBox) {
-    let (mx, my) = window.get_mouse_pos(MouseMode::Clamp).unwrap();
-    if window.get_mouse_down(MouseButton::Left) {
-        world.add_particle(ParticleType::Sand, mx as usize, my as usize);
-    }
-    if window.get_mouse_down(MouseButton::Right) {
-        world.add_particle(ParticleType::Water, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::I) {
-        println!("particle here: {:?}", world.get(mx as usize, my as usize));
-    }
-    if window.is_key_down(Key::C) {
-        world.add_cluster(ParticleType::Sand, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::W) {
-        world.add_cluster(ParticleType::Water, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::S) {
-        world.add_cluster(ParticleType::Stone, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::Key3) {
-        world.add_cluster(ParticleType::Smoke, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::G) {
-        world.add_cluster(ParticleType::Empty, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::A) {
-        world.add_cluster(ParticleType::Gravel, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::D) {
-        world.add_cluster(ParticleType::Wood, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::O) {
-        world.add_cluster(ParticleType::Oil, mx as usize, my as usize);
-    }
-    if window.is_key_down(Key::R) {
-        world.clear();
-    }
-    if window.is_key_down(Key::P) {
-        window.update_with_buffer(&world.to_debug(), world.width, world.height).expect("window update");
-        std::thread::sleep(Duration::from_millis(100));
-    }
-    if window.is_key_down(Key::Equal) {
-        world.thread_count += 1;
-        std::thread::sleep(Duration::from_millis(100));
-    }
-    if window.is_key_down(Key::Minus) {
-        world.thread_count -= 1;
-        std::thread::sleep(Duration::from_millis(100));
-    }
-    if window.is_key_down(Key::Key1) {
-        world.cluster_size -= 1;
-    }
-    if window.is_key_down(Key::Key2) {
-        world.cluster_size += 1;
-    }
-}
-
-pub fn color_near(red: u8, green: u8, blue: u8, randvar: u32, timevar: u32, time: u32) -> u32 {
-    use rand::random;
-
-    let offset = |base: u8| {
-        let delta = (random::<i8>() % (randvar as i8)) as i16;
-        let angle = 2. * 3.14199 * (time as f32 / timevar as f32);
+/// scatters each channel up to `randvar - 1` either way and shifts it over a
+/// period of `timevar` ticks. a variance of 0 turns that part off
+pub fn color_near(rng: &mut Pcg, red: u8, green: u8, blue: u8, randvar: u32, timevar: u32, time: u32) -> u32 {
+    let randvar = randvar.min(127);
+    let mut offset = |base: u8| {
+        let roll = rng.next_u32();
+        let delta = match randvar {
+            0 => 0,
+            _ => (roll % (2 * randvar - 1)) as i16 - (randvar - 1) as i16,
+        };
+        let angle = match timevar {
+            0 => 0.,
+            _ => 2. * 3.14199 * (time as f32 / timevar as f32),
+        };
         let gamma = (angle.sin() * (randvar as f32 / 2.)).round() as i16;
         (base as i16 + delta + gamma).clamp(0, 255) as u8
     };
@@ -152,9 +93,9 @@ pub fn greatest_common_divisor(mut rhs: usize, mut lhs: usize) -> usize {
     rhs
 }
 
-pub fn random_coprime(target: usize) -> usize {
+pub fn random_coprime(target: usize, rng: &mut Pcg) -> usize {
     loop {
-        let candidate = random_range(1..target);
+        let candidate = rng.random_range(1..target);
         if greatest_common_divisor(candidate, target) == 1 {
             return candidate;
         }
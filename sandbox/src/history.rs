@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::io::Result;
+use std::rc::Rc;
+
+use crate::particles::Particle;
+use crate::sandbox::SandBox;
+
+/// how much the undo and redo steps may hold before the oldest ones go. a
+/// snapshot of a 600 by 500 world is a couple of megabytes
+pub const DEFAULT_BUDGET: usize = 128 << 20;
+
+type Fan = Option<(f32, f32)>;
+
+/// what undoing or redoing brings the world back to
+enum Step {
+    /// the whole world at some point, and the edits made on top of it before
+    /// the world ticked again
+    Snapshot(Snapshot),
+    /// every cell and fan an edit wrote with what it held before and after.
+    /// only right while nothing else has changed, so these are turned into
+    /// snapshots before the next tick
+    Journal(Journal),
+}
+
+#[derive(Clone)]
+struct Snapshot {
+    /// the world run length encoded like a save file, shared by every edit
+    /// made between the same two ticks
+    bytes: Rc<Vec<u8>>,
+    replay: Vec<Journal>,
+}
+
+#[derive(Clone)]
+struct Journal {
+    cells: Vec<(usize, Particle, Particle)>,
+    fans: Vec<(usize, Fan, Fan)>,
+}
+
+/// the edit being made right now
+#[derive(Default)]
+struct Edit {
+    /// what every cell and fan held when the edit first wrote to it
+    cells: HashMap<usize, Particle>,
+    fans: HashMap<usize, Fan>,
+    /// the world from before the edit, once something forced it to be taken.
+    /// nothing else needs recording after that
+    snapshot: Option<Snapshot>,
+}
+
+/// undo and redo for everything painted into the world. an edit is kept as a
+/// journal of the cells it wrote, which is cheap and exact as long as the
+/// world stands still. the first tick after any edits takes one snapshot of
+/// the world from before them, so the history holds a full copy of the world
+/// at most once per tick that follows painting rather than once per stroke
+pub struct History {
+    /// bytes the steps may take up before the oldest ones are dropped
+    pub budget: usize,
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    edit: Option<Edit>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History { budget: DEFAULT_BUDGET, undo: VecDeque::new(), redo: Vec::new(), edit: None }
+    }
+}
+
+impl History {
+    pub fn build() -> Self {
+        Self::default()
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.edit.is_some()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// bytes the steps take up right now
+    pub fn size(&self) -> usize {
+        self.undo.iter().chain(&self.redo).map(Step::size).sum()
+    }
+
+    pub(crate) fn record_cell(&mut self, idx: usize, before: Particle) {
+        if let Some(edit) = self.edit.as_mut().filter(|edit| edit.snapshot.is_none()) {
+            edit.cells.entry(idx).or_insert(before);
+        }
+    }
+
+    pub(crate) fn record_fan(&mut self, idx: usize, before: Fan) {
+        if let Some(edit) = self.edit.as_mut().filter(|edit| edit.snapshot.is_none()) {
+            edit.fans.entry(idx).or_insert(before);
+        }
+    }
+
+    pub(crate) fn drop_edit(&mut self) {
+        self.edit = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.edit = None;
+    }
+
+    /// drops the oldest undo steps first and then the furthest redo steps
+    fn trim(&mut self) {
+        let mut size = self.size();
+        while size > self.budget {
+            let dropped = match self.undo.pop_front() {
+                Some(step) => step,
+                None if !self.redo.is_empty() => self.redo.remove(0),
+                None => return,
+            };
+            size -= dropped.size();
+        }
+    }
+}
+
+impl Step {
+    /// a snapshot shared between steps is counted once across all of them
+    fn size(&self) -> usize {
+        match self {
+            Self::Snapshot(snapshot) => {
+                snapshot.bytes.len() / Rc::strong_count(&snapshot.bytes)
+                    + snapshot.replay.iter().map(Journal::size).sum::<usize>()
+            }
+            Self::Journal(journal) => journal.size(),
+        }
+    }
+
+    fn into_journal(self) -> Option<Journal> {
+        match self {
+            Self::Journal(journal) => Some(journal),
+            Self::Snapshot(_) => None,
+        }
+    }
+}
+
+impl Journal {
+    /// what the open edit wrote so far, with the world as it is now on the
+    /// after side
+    fn of_edit(edit: &Edit, world: &SandBox) -> Self {
+        Journal {
+            cells: edit.cells.iter().map(|(&idx, &before)| (idx, before, world.grid[idx])).collect(),
+            fans: edit.fans.iter().map(|(&idx, &before)| (idx, before, world.wind.fans[idx])).collect(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.cells.len() * size_of::<(usize, Particle, Particle)>() + self.fans.len() * size_of::<(usize, Fan, Fan)>()
+    }
+
+    /// puts either the after or the before side of the edit into the world.
+    /// tiles are only woken for a real undo or redo, when a snapshot is being
+    /// taken everything gets put back before anyone looks
+    fn write(&self, world: &mut SandBox, forward: bool, wake: bool) {
+        self.cells.iter().for_each(|&(idx, before, after)| {
+            world.grid[idx] = if forward { after } else { before };
+            if wake {
+                world.tiles.wake(idx % world.width, idx / world.width);
+            }
+        });
+        self.fans.iter().for_each(|&(idx, before, after)| {
+            world.wind.fans[idx] = if forward { after } else { before };
+            world.wind.calm = false;
+        });
+    }
+}
+
+impl SandBox {
+    /// everything painted until [`SandBox::end_edit`] becomes one undo step.
+    /// does nothing when an edit is already open, so a clear in the middle of
+    /// a stroke just joins it
+    pub fn begin_edit(&mut self) {
+        if self.history.edit.is_none() {
+            self.history.edit = Some(Edit::default());
+        }
+    }
+
+    pub fn end_edit(&mut self) {
+        let Some(edit) = self.history.edit.take()
+        else {
+            return;
+        };
+        let step = match edit.snapshot {
+            Some(snapshot) => Step::Snapshot(snapshot),
+            // nothing was written
+            None if edit.cells.is_empty() && edit.fans.is_empty() => return,
+            None => Step::Journal(Journal::of_edit(&edit, self)),
+        };
+        self.history.redo.clear();
+        self.history.undo.push_back(step);
+        self.history.trim();
+    }
+
+    /// goes back to how the world was right before the last edit, even if it
+    /// has kept running since. returns whether there was anything to undo
+    pub fn undo(&mut self) -> Result<bool> {
+        self.end_edit();
+        let Some(step) = self.history.undo.pop_back()
+        else {
+            return Ok(false);
+        };
+        let inverse = self.restore(step, false)?;
+        self.history.redo.push(inverse);
+        self.history.trim();
+        Ok(true)
+    }
+
+    pub fn redo(&mut self) -> Result<bool> {
+        self.end_edit();
+        let Some(step) = self.history.redo.pop()
+        else {
+            return Ok(false);
+        };
+        let inverse = self.restore(step, true)?;
+        self.history.undo.push_back(inverse);
+        self.history.trim();
+        Ok(true)
+    }
+
+    /// brings the step back and hands out the step that returns to the world
+    /// as it was. a snapshot that doesn't decode any more (the materials
+    /// changed under it) takes the whole history with it
+    fn restore(&mut self, step: Step, forward: bool) -> Result<Step> {
+        match step {
+            Step::Journal(journal) => {
+                journal.write(self, forward, true);
+                Ok(Step::Journal(journal))
+            }
+            Step::Snapshot(snapshot) => {
+                let current = self.encode();
+                if let Err(err) = self.decode(&snapshot.bytes) {
+                    self.history.clear();
+                    return Err(err);
+                }
+                snapshot.replay.iter().for_each(|journal| journal.write(self, true, true));
+                Ok(Step::Snapshot(Snapshot { bytes: Rc::new(current), replay: Vec::new() }))
+            }
+        }
+    }
+
+    /// makes sure the open edit has a snapshot from before it, for edits that
+    /// rewrite too much of the world to journal cell by cell. whatever the edit
+    /// already wrote is taken back for the snapshot and put in again after
+    pub(crate) fn snapshot_edit(&mut self) {
+        let journal = match &self.history.edit {
+            Some(edit) if edit.snapshot.is_none() => Journal::of_edit(edit, self),
+            _ => return,
+        };
+        journal.write(self, false, false);
+        let bytes = Rc::new(self.encode());
+        journal.write(self, true, false);
+        if let Some(edit) = self.history.edit.as_mut() {
+            edit.snapshot = Some(Snapshot { bytes, replay: Vec::new() });
+        }
+    }
+
+    /// turns every journal made since the last tick into a snapshot, since
+    /// the tick is about to leave them with nothing to go back to. the undo
+    /// side shares one snapshot from before the first of them and replays the
+    /// ones in between, the redo side shares one of the world as it is now.
+    /// costs nothing when nothing was edited
+    pub(crate) fn settle_history(&mut self) {
+        let mut history = std::mem::take(&mut self.history);
+
+        let count = history.redo.iter().rev().take_while(|step| matches!(step, Step::Journal(_))).count();
+        if count > 0 {
+            let split = history.redo.len() - count;
+            // the next one to redo sits at the end
+            let journals: Vec<Journal> = history.redo.drain(split..).rev().filter_map(Step::into_journal).collect();
+            let bytes = Rc::new(self.encode());
+            let snapshots: Vec<Step> = (1..=journals.len())
+                .map(|end| Step::Snapshot(Snapshot { bytes: bytes.clone(), replay: journals[..end].to_vec() }))
+                .collect();
+            history.redo.extend(snapshots.into_iter().rev());
+        }
+
+        let count = history.undo.iter().rev().take_while(|step| matches!(step, Step::Journal(_))).count();
+        let open = history.edit.as_ref().filter(|edit| edit.snapshot.is_none());
+        let open = open.filter(|edit| !edit.cells.is_empty() || !edit.fans.is_empty());
+        if count > 0 || open.is_some() {
+            let split = history.undo.len() - count;
+            let journals: Vec<Journal> = history.undo.drain(split..).filter_map(Step::into_journal).collect();
+            let edit = open.map(|edit| Journal::of_edit(edit, self));
+            journals.iter().chain(&edit).rev().for_each(|journal| journal.write(self, false, false));
+            let bytes = Rc::new(self.encode());
+            journals.iter().chain(&edit).for_each(|journal| journal.write(self, true, false));
+            history.undo.extend((0..journals.len()).map(|end| {
+                Step::Snapshot(Snapshot { bytes: bytes.clone(), replay: journals[..end].to_vec() })
+            }));
+            if let Some(edit) = history.edit.as_mut().filter(|_| edit.is_some()) {
+                edit.snapshot = Some(Snapshot { bytes, replay: journals });
+            }
+        }
+
+        self.history = history;
+        self.history.trim();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    fn square(world: &mut SandBox, species: ParticleType, x: usize, y: usize) {
+        world.begin_edit();
+        (y..y + 9).for_each(|y| (x..x + 9).for_each(|x| world.put_particle(species, x, y)));
+        world.end_edit();
+    }
+
+    fn world() -> SandBox {
+        let mut world = SandBox::build_seeded(60, 40, 1);
+        (0..10).for_each(|y| world.put_particle(ParticleType::GRAVEL, 50, y));
+        world
+    }
+
+    #[test]
+    fn undo_before_a_tick_takes_back_only_the_stroke() {
+        let mut world = world();
+        (0..20).for_each(|_| world.update());
+        let (before, tick) = (world.to_color(), world.tick);
+        square(&mut world, ParticleType::STONE, 5, 31);
+        assert!(world.undo().expect("journals always undo"));
+        assert!(world.to_color() == before);
+        assert!(world.tick == tick);
+        assert!(!world.undo().expect("nothing left to undo"));
+    }
+
+    #[test]
+    fn falling_strokes_undo_and_redo_whole() {
+        let mut world = world();
+        let start = world.census();
+        square(&mut world, ParticleType::SAND, 5, 0);
+        (0..100).for_each(|_| world.update());
+        let sanded = world.census();
+        square(&mut world, ParticleType::WATER, 30, 0);
+        (0..200).for_each(|_| world.update_par());
+        let end = world.census();
+        assert!(end[ParticleType::SAND.index()] == 81 && end[ParticleType::WATER.index()] == 81);
+
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.census() == sanded);
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.census() == start);
+        world.redo().expect("the snapshot still decodes");
+        assert!(world.census() == sanded);
+        // the world ran on after the undo, redo still doesn't paint anything twice
+        (0..50).for_each(|_| world.update());
+        world.redo().expect("the snapshot still decodes");
+        assert!(world.census() == end);
+    }
+
+    #[test]
+    fn strokes_between_the_same_ticks_undo_one_at_a_time() {
+        let mut world = world();
+        let start = world.census();
+        square(&mut world, ParticleType::SAND, 5, 0);
+        let sanded = world.to_color();
+        square(&mut world, ParticleType::WATER, 30, 0);
+        (0..50).for_each(|_| world.update());
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.to_color() == sanded);
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.census() == start);
+    }
+
+    #[test]
+    fn a_stroke_held_across_ticks_is_one_step() {
+        let mut world = world();
+        let start = world.census();
+        world.begin_edit();
+        world.put_particle(ParticleType::SAND, 5, 0);
+        (0..10).for_each(|_| world.update());
+        world.put_particle(ParticleType::SAND, 6, 0);
+        world.end_edit();
+        (0..10).for_each(|_| world.update());
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.census() == start);
+    }
+
+    #[test]
+    fn a_new_edit_drops_the_redo_steps() {
+        let mut world = world();
+        square(&mut world, ParticleType::SAND, 5, 0);
+        world.undo().expect("journals always undo");
+        assert!(world.history.can_redo());
+        square(&mut world, ParticleType::WATER, 30, 0);
+        assert!(!world.history.can_redo());
+    }
+
+    #[test]
+    fn undoing_a_clear_brings_the_whole_world_back() {
+        let mut world = world();
+        square(&mut world, ParticleType::SAND, 5, 0);
+        (0..5).for_each(|_| world.update());
+        let before = world.to_color();
+        world.clear();
+        assert!(world.census()[ParticleType::EMPTY.index()] == 60 * 40);
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.to_color() == before);
+    }
+
+    #[test]
+    fn a_clear_joining_a_stroke_undoes_both() {
+        let mut world = world();
+        let before = world.to_color();
+        world.begin_edit();
+        world.put_particle(ParticleType::WATER, 20, 20);
+        world.clear();
+        world.end_edit();
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.to_color() == before);
+    }
+}
@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::io::Write;
+use std::path::Path;
+
+use crate::save::MAX_AREA;
+use crate::save::MAX_SIDE;
+
+/// plain 0xAARRGGBB pixel buffer, the same layout the window and
+/// `SandBox::frame` use
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    /// picks the decoder from the file extension, anything that isn't png is
+    /// treated as ppm
+    pub fn read(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => Self::read_png(path),
+            _ => Self::read_ppm(path),
+        }
+    }
+
+    /// binary (P6) and ascii (P3) ppm, any max value
+    pub fn read_ppm(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let mut position = 0;
+        let magic = ppm_token(&bytes, &mut position);
+        let number = |word: &str| word.parse::<usize>().map_err(|_| invalid("malformed ppm header"));
+        let width = number(ppm_token(&bytes, &mut position))?;
+        let height = number(ppm_token(&bytes, &mut position))?;
+        let maxval = number(ppm_token(&bytes, &mut position))?.clamp(1, u16::MAX as usize);
+        let area = width.checked_mul(height).filter(|&area| area <= MAX_AREA);
+        let area = area
+            .filter(|_| width <= MAX_SIDE && height <= MAX_SIDE)
+            .ok_or_else(|| invalid(&format!("ppm size {}x{} is out of range", width, height)))?;
+        let channels: Vec<usize> = match magic {
+            "P6" => {
+                // exactly one whitespace byte separates the header from the data
+                let data = bytes.get(position + 1..).unwrap_or_default();
+                if maxval < 256 {
+                    data.iter().map(|&byte| byte as usize).collect()
+                }
+                else {
+                    data.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize).collect()
+                }
+            }
+            "P3" => {
+                // the header alone isn't trusted with the allocation
+                let mut values = Vec::new();
+                (0..area * 3).try_for_each(|_| {
+                    values.push(number(ppm_token(&bytes, &mut position))?);
+                    Ok::<(), Error>(())
+                })?;
+                values
+            }
+            _ => return Err(invalid("only P3 and P6 ppm files are supported")),
+        };
+        if channels.len() < area * 3 {
+            return Err(invalid("ppm pixel data is truncated"));
+        }
+
+        let scale = |value: usize| (value.min(maxval) * 255 / maxval) as u32;
+        let pixels = channels
+            .chunks_exact(3)
+            .take(area)
+            .map(|rgb| 0xff000000 | scale(rgb[0]) << 16 | scale(rgb[1]) << 8 | scale(rgb[2]))
+            .collect();
+        Ok(Image { width, height, pixels })
+    }
+
+    pub fn read_png(path: &Path) -> Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size().ok_or_else(|| invalid("png is too large"))?];
+        let info = reader.next_frame(&mut buffer)?;
+        let data = &buffer[..info.buffer_size()];
+
+        let argb = |red: u8, green: u8, blue: u8, alpha: u8| {
+            (alpha as u32) << 24 | (red as u32) << 16 | (green as u32) << 8 | blue as u32
+        };
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data.chunks_exact(4).map(|px| argb(px[0], px[1], px[2], px[3])).collect(),
+            png::ColorType::Rgb => data.chunks_exact(3).map(|px| argb(px[0], px[1], px[2], 255)).collect(),
+            png::ColorType::GrayscaleAlpha => {
+                data.chunks_exact(2).map(|px| argb(px[0], px[0], px[0], px[1])).collect()
+            }
+            png::ColorType::Grayscale => data.iter().map(|&px| argb(px, px, px, 255)).collect(),
+            png::ColorType::Indexed => return Err(invalid("indexed png was not expanded")),
+        };
+        Ok(Image { width: info.width as usize, height: info.height as usize, pixels })
+    }
+}
+
+/// writes 0xAARRGGBB pixels out as a binary ppm, alpha is dropped
+pub fn write_ppm(path: &Path, width: usize, height: usize, pixels: &[u32]) -> Result<()> {
+    {
+        debug_assert!(pixels.len() == width * height);
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    pixels.iter().try_for_each(|&pixel| {
+        out.write_all(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8])
+    })?;
+    out.flush()
+}
+
+pub fn write_png(path: &Path, width: usize, height: usize, pixels: &[u32]) -> Result<()> {
+    {
+        debug_assert!(pixels.len() == width * height);
+    }
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let data: Vec<u8> =
+        pixels.iter().flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]).collect();
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// png or ppm depending on the extension, same as `Image::read`
+pub fn write_image(path: &Path, width: usize, height: usize, pixels: &[u32]) -> Result<()> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("png") => write_png(path, width, height, pixels),
+        _ => write_ppm(path, width, height, pixels),
+    }
+}
+
+/// header tokens are whitespace separated and `#` comments run to the end of
+/// the line
+fn ppm_token<'a>(bytes: &'a [u8], position: &mut usize) -> &'a str {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|&byte| byte != b'\n') {
+                    *position += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            _ => break,
+        }
+    }
+    let start = *position;
+    while bytes.get(*position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+        *position += 1;
+    }
+    std::str::from_utf8(&bytes[start..*position]).unwrap_or_default()
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("sandbox-{}-{}", std::process::id(), name))
+    }
+
+    /// reads the bytes back through a file that's gone again afterwards
+    fn read(name: &str, bytes: &[u8]) -> Result<Image> {
+        let path = scratch(name);
+        std::fs::write(&path, bytes)?;
+        let image = Image::read(&path);
+        std::fs::remove_file(&path)?;
+        image
+    }
+
+    #[test]
+    fn reads_binary_and_ascii_ppm() {
+        let mut binary = b"P6\n# a comment\n2 1\n255\n".to_vec();
+        binary.extend_from_slice(&[255, 0, 0, 0, 128, 255]);
+        let image = read("binary.ppm", &binary).expect("valid p6");
+        assert!(image.width == 2 && image.height == 1);
+        assert!(image.pixels == [0xffff0000, 0xff0080ff]);
+
+        // any max value gets scaled up to 255
+        let image = read("ascii.ppm", b"P3 1 2 15\n15 0 0\n0 15 0\n").expect("valid p3");
+        assert!(image.pixels == [0xffff0000, 0xff00ff00]);
+    }
+
+    #[test]
+    fn broken_ppm_headers_are_errors() {
+        let huge = format!("P6 {} {} 255\n", usize::MAX, usize::MAX);
+        assert!(read("overflow.ppm", huge.as_bytes()).is_err());
+        assert!(read("wide.ppm", b"P3 100000 1 255\n0 0 0\n").is_err());
+        assert!(read("big.ppm", b"P3 8000 8000 255\n0 0 0\n").is_err());
+        assert!(read("short.ppm", b"P6 2 2 255\n\x00\x00\x00").is_err());
+        assert!(read("magic.ppm", b"P5 1 1 255\n\x00").is_err());
+    }
+
+    #[test]
+    fn written_images_read_back() {
+        let pixels = [0xff102030, 0xffffffff, 0xff000000, 0xff7f8081, 0xffabcdef, 0xff010203];
+        ["round.ppm", "round.png"].into_iter().for_each(|name| {
+            let path = scratch(name);
+            write_image(&path, 3, 2, &pixels).expect("temp dir is writable");
+            let image = Image::read(&path).expect("just written");
+            std::fs::remove_file(&path).expect("just written");
+            assert!(image.width == 3 && image.height == 2);
+            assert!(image.pixels == pixels);
+        });
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+
+use crate::image::Image;
+use crate::materials::Materials;
+use crate::particles::ParticleType;
+use crate::sandbox::SandBox;
+
+/// explicit color to species mapping for level images, one entry per line:
+///
+/// ```text
+/// // comments start with two slashes
+/// #f5d2aa sand
+/// 3060c0  water
+/// ```
+pub struct Legend {
+    pub entries: Vec<(u32, ParticleType)>,
+}
+
+impl Legend {
+    pub fn read(path: &Path, materials: &Materials) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?, materials)
+    }
+
+    pub fn parse(source: &str, materials: &Materials) -> Result<Self> {
+        let entries = source
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.split("//").next().unwrap_or_default().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(number, line)| {
+                let invalid =
+                    |message: &str| Error::new(ErrorKind::InvalidData, format!("line {}: {}", number, message));
+                let mut words = line.split_whitespace();
+                let color = words.next().unwrap_or_default().trim_start_matches('#');
+                let color = u32::from_str_radix(color, 16)
+                    .ok()
+                    .filter(|_| color.len() == 6)
+                    .ok_or_else(|| invalid("expected an rrggbb color"))?;
+                let name = words.next().ok_or_else(|| invalid("missing species"))?;
+                let species = materials.find(name).ok_or_else(|| invalid("unknown species"))?;
+                Ok((0xff000000 | color, species))
+            })
+            .collect::<Result<Vec<(u32, ParticleType)>>>()?;
+
+        Ok(Legend { entries })
+    }
+
+    /// every material that can be painted, matched on its base color
+    pub fn from_materials(materials: &Materials) -> Self {
+        let entries =
+            materials.paintable().filter_map(|(species, material)| Some((material.color?.base(), species))).collect();
+        Legend { entries }
+    }
+
+    pub fn nearest(&self, pixel: u32) -> Option<ParticleType> {
+        self.entries.iter().min_by_key(|&&(color, _)| color_distance(color, pixel)).map(|&(_, species)| species)
+    }
+}
+
+impl SandBox {
+    /// paints an image into the world from the top left corner, one particle
+    /// per pixel into the empty cells, so it goes into the history like
+    /// painting by hand. each pixel becomes whichever legend entry it's
+    /// closest to, or the nearest material base color without a legend.
+    /// transparent pixels are left alone. the level is taken as drawn, so
+    /// stone left floating in it stays up until something touches it
+    pub fn import_level(&mut self, image: &Image, legend: Option<&Legend>) {
+        let materials = Legend::from_materials(&self.materials);
+        let legend = legend.unwrap_or(&materials);
+        let mut matches: HashMap<u32, Option<ParticleType>> = HashMap::new();
+
+        (0..image.height.min(self.height)).for_each(|y| {
+            (0..image.width.min(self.width)).for_each(|x| {
+                let pixel = image.pixels[y * image.width + x];
+                if pixel >> 24 < 0x80 {
+                    return;
+                }
+                let species = *matches.entry(pixel | 0xff000000).or_insert_with(|| legend.nearest(pixel));
+                let index = y * self.width + x;
+                if let Some(species) = species
+                    && self.grid[index].is_empty()
+                {
+                    // drawn cells start out as whatever they are rather than
+                    // falling, so the level comes up the way it was drawn
+                    self.put_particle(species, x, y);
+                    self.grid[index].stop_falling(&self.materials);
+                }
+            });
+        });
+        self.structure.stale = true;
+    }
+}
+
+fn color_distance(lhs: u32, rhs: u32) -> u32 {
+    let channel = |shift: u32| ((lhs >> shift) & 0xff).abs_diff((rhs >> shift) & 0xff).pow(2);
+    channel(16) + channel(8) + channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legends_parse_with_comments() {
+        let materials = Materials::builtin();
+        let legend = Legend::parse("// sand and water\n#f5d2aa sand\n\n3060c0  water // the sea\n", &materials)
+            .expect("valid legend");
+        assert!(legend.entries == [(0xfff5d2aa, ParticleType::SAND), (0xff3060c0, ParticleType::WATER)]);
+    }
+
+    #[test]
+    fn broken_legend_lines_are_errors() {
+        let materials = Materials::builtin();
+        ["#f5d2a sand", "#zzzzzz sand", "#f5d2aa", "#f5d2aa unobtainium"].into_iter().for_each(|line| {
+            let err = Legend::parse(&format!("// first\n{}", line), &materials).err().expect("broken line");
+            assert!(err.to_string().starts_with("line 2:"));
+        });
+    }
+
+    #[test]
+    fn pixels_match_the_nearest_entry() {
+        let legend = Legend { entries: vec![(0xff000000, ParticleType::STONE), (0xffffffff, ParticleType::SAND)] };
+        assert!(legend.nearest(0xff202020) == Some(ParticleType::STONE));
+        assert!(legend.nearest(0xffe0e0e0) == Some(ParticleType::SAND));
+        assert!(Legend { entries: Vec::new() }.nearest(0xff202020).is_none());
+
+        // without a legend every material comes back from its own base color
+        let materials = Materials::builtin();
+        let legend = Legend::from_materials(&materials);
+        legend.entries.iter().for_each(|&(color, species)| {
+            assert!(materials.get(legend.nearest(color).expect("not empty")).color.map(|color| color.base())
+                == materials.get(species).color.map(|color| color.base()));
+        });
+    }
+}
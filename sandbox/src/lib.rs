@@ -0,0 +1,41 @@
+//! falling sand simulation. build a [`SandBox`], paint particles into it with
+//! [`SandBox::add_particle`] or [`SandBox::add_cluster`], step it with
+//! [`SandBox::update`] or [`SandBox::update_par`], and read the pixels back
+//! with [`SandBox::frame`]
+
+pub mod chunks;
+pub mod font;
+pub mod helpers;
+pub mod history;
+pub mod image;
+pub mod level;
+pub mod materials;
+pub mod overlay;
+pub mod particle_params;
+pub mod particle_updates;
+pub mod particles;
+pub mod pcg;
+pub mod post_process;
+pub mod recording;
+pub mod sandbox;
+pub mod tools;
+pub mod wind;
+
+mod combustion;
+mod heat;
+mod reactions;
+mod render;
+mod save;
+mod structure;
+mod thread_pool;
+mod thread_ptr;
+mod tiles;
+
+pub use materials::Material;
+pub use materials::Materials;
+pub use particle_params::ParticleParams;
+pub use particle_updates::Behavior;
+pub use particle_updates::Update;
+pub use particles::Particle;
+pub use particles::ParticleType;
+pub use sandbox::SandBox;
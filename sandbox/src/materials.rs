@@ -0,0 +1,528 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::particle_params::ParticleParams;
+use crate::particle_updates::Behavior;
+use crate::particle_updates::Burning;
+use crate::particle_updates::Gas;
+use crate::particle_updates::Liquid;
+use crate::particle_updates::Solid;
+use crate::particles::Particle;
+use crate::particles::ColorSpec;
+use crate::particles::ParticleType;
+use crate::pcg::Pcg;
+use crate::sandbox::SandBox;
+
+/// the built in table, see the comments at the top of the file for the format
+const BUILTIN_MATERIALS: &str = include_str!("../materials.toml");
+
+/// species are stored in a byte
+const MAX_MATERIALS: usize = 256;
+
+/// color scatter is worked out on single bytes
+const COLOR_VARIANCE: RangeInclusive<u32> = 0..=127;
+
+/// what every material starts at unless its entry says otherwise
+pub const AMBIENT_TEMPERATURE: f32 = 20.;
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    /// what the particle does once it isn't free falling, `None` never moves
+    pub behavior: Option<Behavior>,
+    pub color: Option<ColorSpec>,
+    pub params: ParticleParams,
+    /// what newly placed particles start at
+    pub temperature: f32,
+    /// turns into another material once hotter than this
+    pub above: Option<PhaseChange>,
+    /// turns into another material once colder than this
+    pub below: Option<PhaseChange>,
+    /// what's left once it has burnt out
+    pub burns_into: ParticleType,
+    /// static materials break into this once nothing holds them up anymore,
+    /// `None` stays hanging in the air
+    pub crumbles_into: Option<ParticleType>,
+    /// holds up every static cell connected to it, like the floor does
+    pub anchor: bool,
+    /// every reaction this material takes part in, seen from its side.
+    /// reactions with a specific partner come before the catch-all ones
+    pub reactions: Vec<Reaction>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhaseChange {
+    pub temperature: f32,
+    pub into: ParticleType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reaction {
+    /// the neighbour it reacts with, `None` reacts with any other material
+    pub with: Option<ParticleType>,
+    /// what this cell becomes
+    pub into: ParticleType,
+    /// what the neighbour becomes
+    pub other_into: ParticleType,
+    /// chance per tick while the two are touching
+    pub probability: f64,
+    /// products start at their material's temperature shifted by this
+    pub heat: f32,
+}
+
+impl Reaction {
+    pub fn matches(&self, here: ParticleType, other: ParticleType) -> bool {
+        match self.with {
+            Some(with) => with == other,
+            None => other != here && other != ParticleType::EMPTY && other != ParticleType::OUT_OF_BOUNDS,
+        }
+    }
+}
+
+/// a reaction as it's written in the file, between two materials in order
+#[derive(Clone, Copy, Debug)]
+struct ReactionRule {
+    first: ParticleType,
+    second: Option<ParticleType>,
+    products: (ParticleType, ParticleType),
+    probability: f64,
+    heat: f32,
+}
+
+impl Material {
+    pub fn color(&self, time: u32, rng: &mut Pcg) -> u32 {
+        match self.color {
+            Some(spec) => spec.sample(time, rng),
+            None => 0xff000000,
+        }
+    }
+}
+
+/// every material the world knows about, indexed by `ParticleType`. the built
+/// in ones always come first in the same order so code can refer to them by
+/// constant, materials from a file are appended after them
+#[derive(Clone, Debug)]
+pub struct Materials {
+    entries: Vec<Material>,
+    rules: Vec<ReactionRule>,
+}
+
+impl Materials {
+    pub fn builtin() -> Self {
+        let mut materials = Materials { entries: Vec::new(), rules: Vec::new() };
+        materials.overlay(BUILTIN_MATERIALS).expect("built in materials are valid");
+        {
+            debug_assert!(materials.len() == ParticleType::OUT_OF_BOUNDS.index());
+        }
+        materials.entries.push(Material {
+            name: "out_of_bounds".to_string(),
+            behavior: Some(Behavior::Solid(Solid)),
+            color: None,
+            params: ParticleParams::default(),
+            temperature: AMBIENT_TEMPERATURE,
+            above: None,
+            below: None,
+            burns_into: ParticleType::EMPTY,
+            crumbles_into: None,
+            anchor: false,
+            reactions: Vec::new(),
+        });
+        materials
+    }
+
+    /// applies every entry in a materials file. an entry replaces the material
+    /// with the same name and keeps its index, so particles already in the
+    /// world stay what they were. new names are appended, and a reaction
+    /// between the same two materials as an existing one replaces it. nothing
+    /// changes if any entry is invalid
+    pub fn overlay(&mut self, source: &str) -> Result<()> {
+        let file: MaterialFile = toml::from_str(source).map_err(|err| invalid(&err.to_string()))?;
+        let mut merged = Materials { entries: self.entries.clone(), rules: self.rules.clone() };
+        // phase changes and burn products can name materials further down the
+        // file, so they are only resolved once every entry is in
+        let mut links = Vec::new();
+        file.material.into_iter().try_for_each(|mut entry| {
+            let names = (entry.above.take(), entry.below.take(), entry.burns_into.take(), entry.crumbles_into.take());
+            let material = entry.into_material()?;
+            if material.name == "out_of_bounds" {
+                return Err(invalid("'out_of_bounds' is reserved"));
+            }
+            let idx = match merged.entries.iter().position(|existing| existing.name == material.name) {
+                Some(idx) => {
+                    merged.entries[idx] = material;
+                    idx
+                }
+                None => {
+                    merged.entries.push(material);
+                    merged.entries.len() - 1
+                }
+            };
+            links.push((idx, names));
+            Ok(())
+        })?;
+        links.into_iter().try_for_each(|(idx, (above, below, burns_into, crumbles_into))| {
+            let find = |name: &str| {
+                merged.find(name).ok_or_else(|| {
+                    invalid(&format!("{}: unknown material '{}'", merged.entries[idx].name, name))
+                })
+            };
+            let resolve = |phase: Option<PhaseEntry>| {
+                phase
+                    .map(|phase| {
+                        let into = find(&phase.into)?;
+                        Ok::<PhaseChange, Error>(PhaseChange { temperature: phase.temperature, into })
+                    })
+                    .transpose()
+            };
+            let (above, below) = (resolve(above)?, resolve(below)?);
+            let burns_into = burns_into.map(|name| find(&name)).transpose()?.unwrap_or(ParticleType::EMPTY);
+            let crumbles_into = crumbles_into.map(|name| find(&name)).transpose()?;
+            // debris that can't fall would just crumble again every tick
+            if crumbles_into.is_some_and(|debris| merged.get(debris).behavior.is_none()) {
+                return Err(invalid(&format!("{}: has to crumble into something that moves", merged.entries[idx].name)));
+            }
+            merged.entries[idx].above = above;
+            merged.entries[idx].below = below;
+            merged.entries[idx].burns_into = burns_into;
+            merged.entries[idx].crumbles_into = crumbles_into;
+            Ok::<(), Error>(())
+        })?;
+        file.reaction.into_iter().try_for_each(|entry| {
+            let rule = entry.into_rule(&merged)?;
+            let same = |existing: &ReactionRule| {
+                (existing.first, existing.second) == (rule.first, rule.second)
+                    || (Some(existing.first), existing.second) == (rule.second, Some(rule.first))
+            };
+            match merged.rules.iter().position(same) {
+                Some(idx) => merged.rules[idx] = rule,
+                None => merged.rules.push(rule),
+            }
+            Ok::<(), Error>(())
+        })?;
+        if merged.len() > MAX_MATERIALS {
+            return Err(invalid(&format!("at most {} materials are supported", MAX_MATERIALS)));
+        }
+        merged.link_reactions();
+        *self = merged;
+        Ok(())
+    }
+
+    /// hands every rule to the materials on both sides of it
+    fn link_reactions(&mut self) {
+        self.entries.iter_mut().for_each(|material| material.reactions.clear());
+        self.rules.iter().for_each(|rule| {
+            let (first, second) = rule.products;
+            self.entries[rule.first.index()].reactions.push(Reaction {
+                with: rule.second,
+                into: first,
+                other_into: second,
+                probability: rule.probability,
+                heat: rule.heat,
+            });
+            if let Some(other) = rule.second.filter(|&other| other != rule.first) {
+                self.entries[other.index()].reactions.push(Reaction {
+                    with: Some(rule.first),
+                    into: second,
+                    other_into: first,
+                    probability: rule.probability,
+                    heat: rule.heat,
+                });
+            }
+        });
+        self.entries.iter_mut().for_each(|material| material.reactions.sort_by_key(|reaction| reaction.with.is_none()));
+    }
+
+    pub fn get(&self, species: ParticleType) -> &Material {
+        &self.entries[species.index()]
+    }
+
+    pub fn find(&self, name: &str) -> Option<ParticleType> {
+        self.entries.iter().position(|material| material.name == name).map(|idx| ParticleType(idx as u8))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ParticleType, &Material)> {
+        self.entries.iter().enumerate().map(|(idx, material)| (ParticleType(idx as u8), material))
+    }
+
+    /// everything that can actually be placed in the world
+    pub fn paintable(&self) -> impl Iterator<Item = (ParticleType, &Material)> {
+        self.iter().filter(|&(species, _)| species != ParticleType::OUT_OF_BOUNDS)
+    }
+}
+
+impl SandBox {
+    /// layers a materials file over the current table, so particles already in
+    /// the world keep their material
+    pub fn load_materials(&mut self, path: &Path) -> Result<()> {
+        let mut materials = self.materials.clone();
+        materials.overlay(&std::fs::read_to_string(path)?)?;
+        self.set_materials(materials);
+        Ok(())
+    }
+
+    /// swaps in a new material table. particles of a material that changed
+    /// pick up its new behavior and color straight away instead of waiting
+    /// until they are next placed, and particles of a material the new table
+    /// doesn't have any more are taken out
+    pub fn set_materials(&mut self, materials: Materials) {
+        // recoloring every cell throws off any journal still waiting on a tick
+        self.settle_history();
+        if materials.len() < self.materials.len() {
+            // undo steps could be holding particles of a material that's gone
+            self.history.clear();
+            let (time, rng) = (self.color_shift, &mut self.rng);
+            self.grid.iter_mut().filter(|particle| particle.species.index() >= materials.len()).for_each(|particle| {
+                *particle = Particle::build_color(ParticleType::EMPTY, time, &materials, rng);
+            });
+        }
+        let changed: Vec<bool> = materials
+            .iter()
+            .map(|(species, material)| {
+                species.index() >= self.materials.len() || {
+                    let previous = self.materials.get(species);
+                    previous.behavior != material.behavior || previous.color != material.color
+                }
+            })
+            .collect();
+        self.materials = materials;
+        let (time, materials, rng) = (self.color_shift, &self.materials, &mut self.rng);
+        self.grid.iter_mut().filter(|particle| changed[particle.species.index()]).for_each(|particle| {
+            if !particle.is_falling() {
+                particle.stop_falling(materials);
+            }
+            particle.color = materials.get(particle.species).color(time, rng);
+        });
+        self.tiles.wake_all();
+    }
+}
+
+/// polls a file's modification time so edits can be picked up while running.
+/// a file that doesn't exist yet counts as changed once it shows up
+pub struct FileWatcher {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FileWatcher {
+    pub fn build(path: &Path) -> Self {
+        let mut watcher = FileWatcher { path: path.to_path_buf(), modified: None };
+        watcher.modified = watcher.stamp();
+        watcher
+    }
+
+    pub fn changed(&mut self) -> bool {
+        let stamp = self.stamp();
+        if stamp.is_none() || stamp == self.modified {
+            return false;
+        }
+        self.modified = stamp;
+        true
+    }
+
+    fn stamp(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|meta| meta.modified()).ok()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialFile {
+    #[serde(default)]
+    material: Vec<MaterialEntry>,
+    #[serde(default)]
+    reaction: Vec<ReactionEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionEntry {
+    reactants: [String; 2],
+    products: [String; 2],
+    probability: f64,
+    #[serde(default)]
+    heat: f32,
+}
+
+impl ReactionEntry {
+    fn into_rule(self, materials: &Materials) -> Result<ReactionRule> {
+        let [first, second] = &self.reactants;
+        let find = |name: &String| {
+            materials.find(name).ok_or_else(|| {
+                invalid(&format!("reaction {} + {}: unknown material '{}'", first, second, name))
+            })
+        };
+        if first == "*" {
+            return Err(invalid("only the second reactant can be '*'"));
+        }
+        let second = if second == "*" {
+            None
+        }
+        else {
+            Some(find(second)?)
+        };
+        Ok(ReactionRule {
+            first: find(first)?,
+            second,
+            products: (find(&self.products[0])?, find(&self.products[1])?),
+            probability: self.probability.clamp(0., 1.),
+            heat: self.heat,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialEntry {
+    name: String,
+    behavior: String,
+    color: Option<ColorSpec>,
+    #[serde(default)]
+    params: ParticleParams,
+    #[serde(default = "ambient_temperature")]
+    temperature: f32,
+    above: Option<PhaseEntry>,
+    below: Option<PhaseEntry>,
+    burns_into: Option<String>,
+    crumbles_into: Option<String>,
+    #[serde(default)]
+    anchor: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhaseEntry {
+    temperature: f32,
+    into: String,
+}
+
+impl MaterialEntry {
+    fn into_material(self) -> Result<Material> {
+        let behavior = match self.behavior.as_str() {
+            "solid" => Some(Behavior::Solid(Solid)),
+            "liquid" => Some(Behavior::Liquid(Liquid)),
+            "gas" => Some(Behavior::Gas(Gas)),
+            "burning" => Some(Behavior::Burning(Burning)),
+            "static" => None,
+            other => return Err(invalid(&format!("{}: unknown behavior '{}'", self.name, other))),
+        };
+        if self.name.is_empty() || self.name.contains(char::is_whitespace) {
+            return Err(invalid(&format!("'{}' is not a valid material name", self.name)));
+        }
+        if let Some(color) = self.color
+            && !(COLOR_VARIANCE.contains(&color.variance) && COLOR_VARIANCE.contains(&color.time_variance))
+        {
+            return Err(invalid(&format!("{}: color variance and time_variance have to be within 0 to 127", self.name)));
+        }
+        Ok(Material {
+            name: self.name,
+            behavior,
+            color: self.color,
+            params: self.params,
+            temperature: self.temperature,
+            above: None,
+            below: None,
+            burns_into: ParticleType::EMPTY,
+            crumbles_into: None,
+            anchor: self.anchor,
+            reactions: Vec::new(),
+        })
+    }
+}
+
+fn ambient_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(color: &str) -> String {
+        format!("[[material]]\nname = \"glass\"\nbehavior = \"solid\"\ncolor = {}\n", color)
+    }
+
+    #[test]
+    fn builtin_table_loads() {
+        let materials = Materials::builtin();
+        assert!(materials.find("sand") == Some(ParticleType::SAND));
+        assert!(materials.find("water") == Some(ParticleType::WATER));
+    }
+
+    #[test]
+    fn new_entries_are_added() {
+        let mut materials = Materials::builtin();
+        let count = materials.len();
+        materials
+            .overlay(&entry("{ red = 200, green = 220, blue = 230, variance = 5, time_variance = 30 }"))
+            .expect("failed to add a valid material");
+        assert!(materials.len() == count + 1);
+        assert!(materials.find("glass").is_some());
+    }
+
+    #[test]
+    fn color_variance_is_checked() {
+        ["variance = 128, time_variance = 30", "variance = 5, time_variance = 200"]
+            .into_iter()
+            .for_each(|variance| {
+                let mut materials = Materials::builtin();
+                let source = entry(&format!("{{ red = 1, green = 2, blue = 3, {} }}", variance));
+                assert!(materials.overlay(&source).is_err(), "accepted {}", variance);
+                assert!(materials.find("glass").is_none());
+            });
+    }
+
+    #[test]
+    fn broken_entries_are_errors() {
+        let mut materials = Materials::builtin();
+        assert!(materials.overlay("[[material]]\nname = \"goo\"\nbehavior = \"wobbly\"\n").is_err());
+        assert!(materials.overlay("[[material]]\nname = \"two words\"\nbehavior = \"solid\"\n").is_err());
+        assert!(materials.overlay("not toml at all [").is_err());
+    }
+
+    #[test]
+    fn zero_variance_colors_stay_put() {
+        let mut materials = Materials::builtin();
+        materials
+            .overlay(&entry("{ red = 10, green = 20, blue = 30, variance = 0, time_variance = 0 }"))
+            .expect("zero variances are allowed");
+        let color = materials.get(materials.find("glass").expect("just added")).color.expect("has a color");
+        let mut rng = Pcg::build(1, 0);
+        (0..100).for_each(|time| assert!(color.sample(time, &mut rng) == color.base()));
+    }
+
+    #[test]
+    fn materials_that_go_away_take_their_particles_with_them() {
+        let mut world = SandBox::build_seeded(20, 20, 1);
+        let mut materials = Materials::builtin();
+        materials
+            .overlay(&entry("{ red = 200, green = 220, blue = 230, variance = 5, time_variance = 30 }"))
+            .expect("failed to add a valid material");
+        let glass = materials.find("glass").expect("just added");
+        world.set_materials(materials);
+        world.put_particle(glass, 5, 19);
+        world.put_particle(ParticleType::SAND, 6, 19);
+        world.set_materials(Materials::builtin());
+        assert!(world.get(5, 19).is_empty());
+        assert!(world.get(6, 19).species == ParticleType::SAND);
+        world.update();
+    }
+}
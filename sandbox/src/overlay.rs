@@ -0,0 +1,200 @@
+use crate::particle_updates::Behavior;
+use crate::sandbox::SandBox;
+
+/// the phases of the checkerboard in the order they run
+const PHASE_COLORS: [u32; 4] = [0xffd04a4a, 0xff4ad04a, 0xff4a6ad0, 0xffd0c04a];
+const BORDER: u32 = 0xffffffff;
+
+/// different ways of looking at the simulation instead of the particle colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overlay {
+    /// awake cells in cyan, sleeping ones black
+    Awake,
+    /// how fast each cell is moving, black standing still up to white at the
+    /// fastest terminal velocity
+    Velocity,
+    /// one flat color per behavior, falling cells stand out from resting ones
+    Behavior,
+    /// the chunks of the last tick tinted by which of the four passes ran them
+    Chunks,
+    /// log scale from the lightest gas to the heaviest solid
+    Density,
+    /// how long every tile took last tick, relative to the slowest. only
+    /// measured while [`SandBox::profile`] is on
+    Cost,
+}
+
+impl Overlay {
+    pub const ALL: [Overlay; 6] =
+        [Overlay::Awake, Overlay::Velocity, Overlay::Behavior, Overlay::Chunks, Overlay::Density, Overlay::Cost];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Awake => "awake",
+            Self::Velocity => "velocity",
+            Self::Behavior => "behavior",
+            Self::Chunks => "chunks",
+            Self::Density => "density",
+            Self::Cost => "cost",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<Overlay> {
+        Self::ALL.into_iter().find(|overlay| overlay.name() == name)
+    }
+
+    /// the overlay after this one, none after the last so cycling passes back
+    /// through the normal view
+    pub fn next(&self) -> Option<Overlay> {
+        Self::ALL.into_iter().skip_while(|overlay| overlay != self).nth(1)
+    }
+}
+
+impl SandBox {
+    /// a fresh frame colored by the overlay instead of the particles
+    pub fn to_debug(&self, overlay: Overlay) -> Vec<u32> {
+        match overlay {
+            Overlay::Awake => self.debug_awake(),
+            Overlay::Velocity => self.debug_velocity(),
+            Overlay::Behavior => self.debug_behavior(),
+            Overlay::Chunks => self.debug_chunks(),
+            Overlay::Density => self.debug_density(),
+            Overlay::Cost => self.debug_cost(),
+        }
+    }
+
+    fn debug_awake(&self) -> Vec<u32> {
+        self.grid
+            .iter()
+            .map(|ele| {
+                if ele.awake {
+                    0xff00ffff
+                }
+                else {
+                    0xff000000
+                }
+            })
+            .collect()
+    }
+
+    fn debug_velocity(&self) -> Vec<u32> {
+        let fastest = self.materials.iter().map(|(_, material)| material.params.terminal_velocity).fold(1., f32::max);
+        self.grid
+            .iter()
+            .map(|particle| heat((particle.vx * particle.vx + particle.vy * particle.vy).sqrt() / fastest))
+            .collect()
+    }
+
+    fn debug_behavior(&self) -> Vec<u32> {
+        self.grid
+            .iter()
+            .map(|particle| match particle.behavior {
+                _ if particle.is_empty() => 0xff000000,
+                None => 0xff808080,
+                Some(Behavior::Solid(_)) => 0xffc8a050,
+                Some(Behavior::Liquid(_)) => 0xff3070e0,
+                Some(Behavior::Gas(_)) => 0xffc080f0,
+                Some(Behavior::FreeFall(_)) => 0xff40f040,
+                Some(Behavior::Burning(_)) => 0xfff03020,
+            })
+            .collect()
+    }
+
+    /// the particles dimmed under their chunk's phase color, with the top and
+    /// left edge of every chunk drawn in
+    fn debug_chunks(&self) -> Vec<u32> {
+        let mut pixels: Vec<u32> = self.grid.iter().map(|particle| blend(particle.color, 0xff000000, 0.6)).collect();
+        self.phases.iter().zip(PHASE_COLORS).for_each(|(phase, tint)| {
+            phase.iter().for_each(|chunk| {
+                (chunk.ymin..chunk.ymax).for_each(|y| {
+                    (chunk.xmin..chunk.xmax).for_each(|x| {
+                        let pixel = &mut pixels[y * self.width + x];
+                        *pixel = if x == chunk.xmin || y == chunk.ymin {
+                            BORDER
+                        }
+                        else {
+                            blend(*pixel, tint, 0.35)
+                        };
+                    });
+                });
+            });
+        });
+        pixels
+    }
+
+    /// densities run from about 0.001 to 100, so each power of ten gets the
+    /// same share of the ramp
+    fn debug_density(&self) -> Vec<u32> {
+        self.grid
+            .iter()
+            .map(|particle| {
+                let density = self.materials.get(particle.species).params.density.max(0.001);
+                heat((density.log10() + 3.) / 5.)
+            })
+            .collect()
+    }
+
+    fn debug_cost(&self) -> Vec<u32> {
+        let spent: Vec<u64> =
+            (0..self.grid.len()).map(|idx| self.tiles.spent(idx % self.width, idx / self.width)).collect();
+        let slowest = spent.iter().copied().max().unwrap_or_default().max(1) as f32;
+        self.grid
+            .iter()
+            .zip(spent)
+            .map(|(particle, spent)| {
+                if spent == 0 {
+                    return blend(particle.color, 0xff000000, 0.8);
+                }
+                heat(spent as f32 / slowest)
+            })
+            .collect()
+    }
+}
+
+/// black through red and yellow up to white as the value goes from 0 to 1
+fn heat(value: f32) -> u32 {
+    let value = value.clamp(0., 1.) * 3.;
+    let red = (value.min(1.) * 255.) as u32;
+    let green = ((value - 1.).clamp(0., 1.) * 255.) as u32;
+    let blue = ((value - 2.).clamp(0., 1.) * 255.) as u32;
+    (0xff << 24) | (red << 16) | (green << 8) | blue
+}
+
+/// moves the color the given share of the way towards the other one
+fn blend(color: u32, other: u32, amount: f32) -> u32 {
+    let mix = |shift: u32| {
+        let (from, to) = ((color >> shift & 0xff) as f32, (other >> shift & 0xff) as f32);
+        ((from + (to - from) * amount) as u32) << shift
+    };
+    (0xff << 24) | mix(16) | mix(8) | mix(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::ParticleType;
+
+    #[test]
+    fn chunks_get_their_borders_drawn_in() {
+        let mut world = SandBox::build_seeded(200, 150, 1);
+        (0..200).step_by(3).for_each(|x| world.put_particle(ParticleType::SAND, x, 20));
+        (0..2).for_each(|_| world.update());
+        let pixels = world.to_debug(Overlay::Chunks);
+
+        let chunks: Vec<_> = world.phases.iter().flatten().copied().collect();
+        assert!(!chunks.is_empty());
+        chunks.iter().for_each(|chunk| {
+            assert!(pixels[chunk.ymin * world.width + chunk.xmin] == BORDER);
+            assert!(pixels[(chunk.ymin + 1) * world.width + chunk.xmin + 1] != BORDER);
+        });
+        // the rest is just the particles dimmed
+        (0..world.grid.len())
+            .filter(|&idx| {
+                let (x, y) = (idx % world.width, idx / world.width);
+                !chunks.iter().any(|chunk| {
+                    (chunk.xmin..chunk.xmax).contains(&x) && (chunk.ymin..chunk.ymax).contains(&y)
+                })
+            })
+            .for_each(|idx| assert!(pixels[idx] == blend(world.grid[idx].color, 0xff000000, 0.6)));
+    }
+}
@@ -1,6 +1,8 @@
-use crate::particles::ParticleType;
+use serde::Deserialize;
 
-#[derive(Default, Clone, Copy)]
+/// the tuning knobs for one material, loaded from the materials file
+#[derive(Default, Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ParticleParams {
     /// when using zig-zag as the update algorithm, particles should never be
     /// permitted to have a terminal velocity of exactly 2. as this interferes
@@ -8,84 +10,46 @@ pub struct ParticleParams {
     pub terminal_velocity: f32,
     pub minimal_velocity: f32,
     pub gravity: f32,
+    /// chance a blocked solid settles down, for gases the share of their speed
+    /// lost every tick
     pub resistance: f64,
     pub volatility: f64,
     pub vertical_affinity: f64,
+    /// how hard gases get pushed around sideways each tick
     pub horizontal_affinity: f64,
     pub fluid_shimmer: f64,
+    /// how freely a liquid runs, also the share of its sideways speed it keeps
+    /// from one tick to the next
     pub viscosity: f64,
-    pub density: usize,
+    /// decides what sinks through and floats on what across every behavior.
+    /// water is 10 and air is 0.012
+    pub density: f32,
+    /// anything moving faster than this turns its speed sideways when it hits
+    /// something head on
     pub speed_to_bounce: f32,
+    /// share of the speed kept through a bounce
     pub horizontal_transfer: f32,
-}
-
-impl ParticleParams {
-    pub fn base_params_builder() -> [Self; ParticleType::EnumLength as usize] {
-        let mut params = [Self::default(); ParticleType::EnumLength as usize];
-        params[ParticleType::Sand as usize] = sand_params();
-        params[ParticleType::Water as usize] = water_params();
-        params[ParticleType::Smoke as usize] = smoke_params();
-        params[ParticleType::Gravel as usize] = gravel_params();
-        params[ParticleType::Oil as usize] = oil_params();
-        params
-    }
-}
-
-fn sand_params() -> ParticleParams {
-    ParticleParams {
-        minimal_velocity: 1.,
-        terminal_velocity: 4.,
-        gravity: 0.05,
-        resistance: 0.2,
-        speed_to_bounce: 1.5,
-        horizontal_transfer: 0.6,
-        ..Default::default()
-    }
-}
-
-fn water_params() -> ParticleParams {
-    ParticleParams {
-        minimal_velocity: 1.,
-        terminal_velocity: 2.5,
-        gravity: 0.05,
-        fluid_shimmer: 0.1,
-        viscosity: 0.85,
-        density: 10,
-        speed_to_bounce: 1.5,
-        horizontal_transfer: 0.6,
-        ..Default::default()
-    }
-}
-
-fn smoke_params() -> ParticleParams {
-    ParticleParams {
-        volatility: 0.001,
-        vertical_affinity: 0.1,
-        horizontal_affinity: 0.5,
-        ..Default::default()
-    }
-}
-
-fn gravel_params() -> ParticleParams {
-    ParticleParams {
-        minimal_velocity: 1.,
-        terminal_velocity: 4.,
-        gravity: 0.1,
-        resistance: 0.8,
-        ..Default::default()
-    }
-}
-
-fn oil_params() -> ParticleParams {
-    ParticleParams {
-        minimal_velocity: 1.,
-        terminal_velocity: 1.5,
-        gravity: 0.01,
-        fluid_shimmer: 0.05,
-        viscosity: 0.1,
-        density: 1,
-        speed_to_bounce: 1.5,
-        horizontal_transfer: 0.6,
-        ..Default::default()
-    }
+    /// share of the temperature difference that flows to a neighbour each
+    /// tick, the lower of the two cells' values is used
+    pub conductivity: f32,
+    /// how much heat it takes to change the temperature by one degree
+    pub heat_capacity: f32,
+    /// share of the difference to the ambient temperature lost every tick
+    pub cooling: f32,
+    /// catches fire at this temperature, zero never burns
+    pub ignition_temperature: f32,
+    /// average number of ticks a burning cell lasts
+    pub burn_duration: f64,
+    /// chance each tick a burning cell puts out smoke above itself
+    pub smoke_yield: f64,
+    /// burning cells never drop below this, so the heat slowly spreads the fire
+    pub flame_temperature: f32,
+    /// chance each tick a burning cell sets a random flammable neighbour alight
+    pub spread: f64,
+    /// share of the air's speed added to the particle every tick, zero and the
+    /// wind goes right past it
+    pub wind: f32,
+    /// how brightly the particle lights up the cells around it, only drawn by
+    /// the glow post process pass
+    pub glow: f32,
 }
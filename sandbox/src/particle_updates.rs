@@ -1,13 +1,11 @@
-use rand::random_bool;
-use rand::random_range;
-
 use crate::helpers::LineTracer;
 use crate::particles::Particle;
 use crate::particles::ParticleType;
+use crate::pcg::Pcg;
 use crate::sandbox::Handler;
 
 pub trait Update {
-    fn update(&self, handler: &mut Handler);
+    fn update(&self, handler: &mut Handler<'_>);
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -22,27 +20,44 @@ pub struct Gas;
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct FreeFall;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Burning;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Behavior {
     Solid(Solid),
     Liquid(Liquid),
     Gas(Gas),
     FreeFall(FreeFall),
+    Burning(Burning),
+}
+
+impl Behavior {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Solid(_) => "solid",
+            Self::Liquid(_) => "liquid",
+            Self::Gas(_) => "gas",
+            Self::FreeFall(_) => "free fall",
+            Self::Burning(_) => "burning",
+        }
+    }
 }
 
 impl Update for Behavior {
-    fn update(&self, handler: &mut Handler) {
+    fn update(&self, handler: &mut Handler<'_>) {
         match self {
             Self::Solid(solid) => solid.update(handler),
             Self::Liquid(liquid) => liquid.update(handler),
             Self::Gas(gas) => gas.update(handler),
             Self::FreeFall(freefall) => freefall.update(handler),
+            Self::Burning(burning) => burning.update(handler),
         }
     }
 }
 
 impl Update for FreeFall {
-    fn update(&self, handler: &mut Handler) {
+    fn update(&self, handler: &mut Handler<'_>) {
         let direc = if handler.here.direction_bias {
             handler.sandbox.deref().flipflop
         }
@@ -77,22 +92,72 @@ impl Update for FreeFall {
             }
             else if handler.here.vy.abs() > params.speed_to_bounce {
                 handler.get_mut_here().vx =
-                    handler.here.vy * direc as f32 * params.horizontal_transfer * random_range(0.0..1.0);
+                    handler.here.vy * direc as f32 * params.horizontal_transfer * handler.rng.random_f32();
                 handler.get_mut_here().vy = params.minimal_velocity;
             }
         }
         if !moved {
-            handler.get_mut_here().vx = 0.;
-            handler.get_mut_here().vy = params.minimal_velocity;
-            handler.get_mut_here().stop_falling();
+            let mut sandbox = handler.sandbox;
+            let here = handler.get_mut_here();
+            here.stop_falling(&sandbox.deref().materials);
+            // a liquid keeps sliding along whatever it landed on, that's what
+            // makes a pour splash out to the sides
+            if !here.is_liquid() {
+                here.vx = 0.;
+            }
+            here.vy = params.minimal_velocity;
+        }
+    }
+}
+
+/// walks the particle one cell at a time along the given velocity for as long
+/// as `passable` lets it. returns whether it got anywhere and the step that
+/// was in the way, if it got stopped
+fn travel(
+    handler: &mut Handler<'_>,
+    vx: f32,
+    vy: f32,
+    passable: impl Fn(&mut Handler<'_>, isize, isize) -> bool,
+) -> (bool, Option<(isize, isize)>) {
+    let mut linetrace = LineTracer::build(handler.x as isize, handler.y as isize, vx, vy);
+    let mut moved = false;
+    while let Some((nx, ny)) = linetrace.step() {
+        let dx = nx - handler.x as isize;
+        let dy = ny - handler.y as isize;
+
+        if dx == 0 && dy == 0 {
+            continue;
+        }
+
+        if !passable(handler, dx, dy) {
+            return (moved, Some((dx, dy)));
         }
+        handler.swap(dx, dy);
+        moved = true;
     }
+    (moved, None)
+}
+
+/// drifting particles are often slower than a cell per tick, so the leftover
+/// fraction is rolled for instead of rounded away
+fn whole_cells(rng: &mut Pcg, speed: f32) -> f32 {
+    let extra = if rng.random_f32() < speed.fract().abs() {
+        speed.signum()
+    }
+    else {
+        0.
+    };
+    speed.trunc() + extra
 }
 
 impl Update for Solid {
-    fn update(&self, handler: &mut Handler) {
+    fn update(&self, handler: &mut Handler<'_>) {
         if !handler.here.is_awake() {
-            if handler.get(0, 1).is_empty() || handler.get(0, 1).is_falling() {
+            if handler.get(0, 1).is_empty()
+                || handler.get(0, 1).is_falling()
+                || handler.sinks_through(0, 1)
+                || (handler.get(0, -1).is_liquid() && handler.lighter_than(0, -1))
+            {
                 handler.get_mut_here().awake = true;
                 handler.get_mut_here().begin_falling();
             }
@@ -115,20 +180,29 @@ impl Update for Solid {
             handler.get_mut_here().begin_falling();
             moved = true;
         }
-        else if handler.get(direc, 1).is_liquid() || handler.get(direc, 1).is_gas() {
+        else if handler.sinks_through(0, 1) {
+            handler.swap(0, 1);
+            moved = true;
+        }
+        else if handler.sinks_through(direc, 1) {
             handler.swap(direc, 1);
             moved = true;
         }
-        else if handler.get(-direc, 1).is_empty()
-            || handler.get(-direc, 1).is_liquid()
-            || handler.get(-direc, 1).is_gas()
-        {
+        else if handler.get(-direc, 1).is_empty() || handler.sinks_through(-direc, 1) {
             handler.swap(-direc, 1);
             moved = true;
         }
-        else if random_bool(params.resistance) {
+        else if handler.get(0, -1).is_liquid() && handler.lighter_than(0, -1) {
+            // anything lighter than the liquid it ended up in floats back up
+            handler.swap(0, -1);
+            moved = true;
+        }
+        else if handler.rng.random_bool(params.resistance) {
             handler.get_mut_here().awake = false;
         }
+        else {
+            handler.keep_active();
+        }
 
         if moved {
             if let Some(p) = handler.get_mut(1, 0) {
@@ -142,7 +216,7 @@ impl Update for Solid {
 }
 
 impl Update for Liquid {
-    fn update(&self, handler: &mut Handler) {
+    fn update(&self, handler: &mut Handler<'_>) {
         let direc = if handler.here.direction_bias {
             handler.sandbox.deref().flipflop
         }
@@ -157,33 +231,49 @@ impl Update for Liquid {
             handler.get_mut_here().begin_falling();
             moved = true;
         }
-        else if handler.get(0, 1).is_gas()
-            || (handler.get(0, 1).is_liquid() && handler.get_params(0, 1).density < params.density)
-        {
+        else if handler.sinks_through(0, 1) {
             handler.swap(0, 1);
             moved = true;
         }
-        else if handler.get(direc, 1).is_empty()
-            || handler.get(direc, 1).is_gas()
-            || (handler.get(direc, 1).is_liquid() && handler.get_params(direc, 1).density < params.density)
-        {
+        else if handler.get(direc, 1).is_empty() || handler.sinks_through(direc, 1) {
             handler.swap(direc, 1);
             moved = true;
         }
-        else if handler.get(-direc, 1).is_empty()
-            || handler.get(-direc, 1).is_gas()
-            || (handler.get(-direc, 1).is_liquid() && handler.get_params(-direc, 1).density < params.density)
-        {
+        else if handler.get(-direc, 1).is_empty() || handler.sinks_through(-direc, 1) {
             handler.swap(-direc, 1);
             moved = true;
         }
 
+        if !moved && handler.here.vx.abs() >= 0.5 {
+            // still carrying momentum from a fall, so it rushes along the
+            // surface instead of spreading out one cell at a time. hitting
+            // something throws it back with whatever the bounce keeps
+            let vx = handler.here.vx.clamp(-params.terminal_velocity, params.terminal_velocity);
+            let (travelled, blocked) = travel(handler, vx, 0., |handler, dx, dy| {
+                handler.get(dx, dy).is_empty() || handler.get(dx, dy).is_gas()
+            });
+            let bounce = if blocked.is_some() {
+                -params.horizontal_transfer
+            }
+            else {
+                1.
+            };
+            let vx = vx * bounce * params.viscosity as f32;
+            handler.get_mut_here().vx = if vx.abs() < 0.5 {
+                0.
+            }
+            else {
+                vx
+            };
+            moved = travelled;
+        }
+
         if !moved {
             loop {
                 if (handler.get(direc, 0).is_empty()
                     || (handler.get(direc, 0).is_liquid()
                         && handler.get(direc, 0).species != handler.here.species))
-                    && (handler.get(-direc, 1).is_liquid() || random_bool(params.viscosity))
+                    && (handler.get(-direc, 1).is_liquid() || handler.rng.random_bool(params.viscosity))
                 {
                     handler.swap(direc, 0);
                     moved = true;
@@ -193,25 +283,143 @@ impl Update for Liquid {
             }
         }
 
-        if !moved && handler.get(0, -1).is_empty() && random_bool(params.fluid_shimmer) {
+        if !moved && handler.get(0, -1).is_empty() && handler.rng.random_bool(params.fluid_shimmer) {
             handler.swap(0, -1);
         }
+        else if !moved
+            && (handler.get(0, -1).is_empty() || handler.get(direc, 0).is_empty() || handler.get(-direc, 0).is_empty())
+        {
+            // still free to shimmer or spread out later, only the roll failed
+            handler.keep_active();
+        }
     }
 }
 
 impl Update for Gas {
-    fn update(&self, handler: &mut Handler) {
-        let direc = handler.sandbox.deref().flipflop;
+    fn update(&self, handler: &mut Handler<'_>) {
         let params = handler.get_params_here();
+        // gas never settles, it just drifts around until it dissipates
+        handler.keep_active();
 
-        if random_bool(params.volatility) {
-            *handler.get_mut_here() = Particle::build(ParticleType::Empty);
+        if handler.rng.random_bool(params.volatility) {
+            *handler.get_mut_here() =
+                Particle::build(ParticleType::EMPTY, &handler.sandbox.deref().materials, handler.rng);
+            return;
         }
-        if handler.get(0, -1).is_empty() && random_bool(params.vertical_affinity) {
+
+        // gases float with a negative gravity and get pushed around a little
+        // every tick, the drag slowly eats whatever speed they had
+        let drag = 1. - params.resistance as f32;
+        let turbulence = (handler.rng.random_f32() * 2. - 1.) * params.horizontal_affinity as f32;
+        let limit = params.terminal_velocity;
+        let mut vx = ((handler.here.vx + turbulence) * drag).clamp(-limit, limit);
+        let mut vy = ((handler.here.vy + params.gravity) * drag).clamp(-limit, limit);
+
+        let (stepx, stepy) = (whole_cells(handler.rng, vx), whole_cells(handler.rng, vy));
+        // gases trade places with each other on the way up or down so the
+        // heavier one always ends up below
+        let (_, blocked) = travel(handler, stepx, stepy, |handler, dx, dy| {
+            let other = handler.get(dx, dy);
+            other.is_empty()
+                || (other.is_gas() && dy > 0 && handler.heavier_than(dx, dy))
+                || (other.is_gas() && dy < 0 && handler.lighter_than(dx, dy))
+        });
+        if let Some((dx, dy)) = blocked {
+            if dy != 0 {
+                // running into a ceiling fast enough spreads the gas out along it
+                if vy.abs() > params.speed_to_bounce {
+                    let side = if vx != 0. {
+                        vx.signum()
+                    }
+                    else if handler.here.direction_bias {
+                        1.
+                    }
+                    else {
+                        -1.
+                    };
+                    vx = (vx + side * vy.abs() * params.horizontal_transfer).clamp(-limit, limit);
+                }
+                vy = 0.;
+            }
+            if dx != 0 {
+                vx = -vx * params.horizontal_transfer;
+            }
+        }
+        let here = handler.get_mut_here();
+        here.vx = vx;
+        here.vy = vy;
+    }
+}
+
+impl Update for Burning {
+    fn update(&self, handler: &mut Handler<'_>) {
+        let params = handler.get_params_here();
+        // nothing about a fire is ever settled
+        handler.keep_active();
+
+        let water = [(0, 1), (1, 0), (-1, 0), (0, -1)]
+            .into_iter()
+            .find(|&(dx, dy)| handler.get(dx, dy).species == ParticleType::WATER);
+        if let Some((dx, dy)) = water {
+            handler.extinguish(dx, dy);
+            return;
+        }
+        if handler.rng.random_bool(1. / params.burn_duration.max(1.)) {
+            handler.burn_out();
+            return;
+        }
+
+        let (dx, dy) = [(0, 1), (1, 0), (-1, 0), (0, -1)][handler.rng.random_range(0..4)];
+        if handler.rng.random_bool(params.spread) {
+            handler.set_alight(dx, dy);
+        }
+
+        let time = handler.sandbox.deref().color_shift;
+        let flame = handler.sandbox.deref().materials.get(ParticleType::FIRE).color(time, handler.rng);
+        let here = handler.get_mut_here();
+        here.temperature = here.temperature.max(params.flame_temperature);
+        here.color = flame;
+        handler.reup_here();
+
+        if handler.get(0, -1).is_empty() && handler.rng.random_bool(params.smoke_yield) {
+            let smoke = Particle::build_color(
+                ParticleType::SMOKE,
+                time,
+                &handler.sandbox.deref().materials,
+                handler.rng,
+            );
+            let temperature = handler.here.temperature;
+            let above = handler.get_mut_unchecked(0, -1);
+            *above = smoke;
+            above.temperature = temperature;
+        }
+        else if handler.get(0, -1).is_empty() && handler.rng.random_bool(params.vertical_affinity) {
             handler.swap(0, -1);
         }
-        else if handler.get(direc, 0).is_empty() && random_bool(params.horizontal_affinity) {
-            handler.swap(direc, 0);
+
+        // loose flames get carried off by the wind, fuel stays put
+        if params.wind > 0. {
+            let (vx, vy) = (handler.here.vx, handler.here.vy);
+            let (stepx, stepy) = (whole_cells(handler.rng, vx), whole_cells(handler.rng, vy));
+            travel(handler, stepx, stepy, |handler, dx, dy| handler.get(dx, dy).is_empty());
+            let drag = 1. - params.resistance as f32;
+            let here = handler.get_mut_here();
+            here.vx = vx * drag;
+            here.vy = vy * drag;
+        }
+
+        // burning fuel still moves the way its material does, so oil keeps
+        // flowing and sawdust keeps falling while they burn. the movement can
+        // hand it its own behavior or free fall, so it's set back afterwards
+        let species = handler.here.species;
+        let base = handler.sandbox.deref().materials.get(species).behavior;
+        if let Some(base) = base.filter(|base| *base != Behavior::Burning(Burning)) {
+            base.update(handler);
+            handler.reup_here();
+            if handler.here.species == species {
+                handler.get_mut_here().behavior = Some(Behavior::Burning(Burning));
+                handler.reup_here();
+            }
         }
     }
 }
@@ -1,11 +1,15 @@
-use rand::random_bool;
+use serde::Deserialize;
 
 use crate::helpers::color_near;
+use crate::materials::AMBIENT_TEMPERATURE;
+use crate::materials::Materials;
 use crate::particle_updates::Behavior;
+use crate::particle_updates::Burning;
 use crate::particle_updates::FreeFall;
 use crate::particle_updates::Gas;
 use crate::particle_updates::Liquid;
 use crate::particle_updates::Solid;
+use crate::pcg::Pcg;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Particle {
@@ -16,43 +20,66 @@ pub struct Particle {
     pub awake: bool,
     pub vx: f32,
     pub vy: f32,
+    /// degrees celsius
+    pub temperature: f32,
 }
 
 #[allow(dead_code)]
 impl Particle {
-    pub fn build(species: ParticleType) -> Self {
+    pub fn build(species: ParticleType, materials: &Materials, rng: &mut Pcg) -> Self {
+        let material = materials.get(species);
         Particle {
             species,
-            behavior: species.base_behavior(),
-            color: ParticleType::color(species, 0),
-            direction_bias: random_bool(0.5),
+            behavior: material.behavior,
+            color: material.color(0, rng),
+            direction_bias: rng.random_bool(0.5),
             awake: true,
             vx: f32::default(),
             vy: f32::default(),
+            temperature: material.temperature,
         }
     }
 
-    pub fn build_color(species: ParticleType, time: u32) -> Self {
+    pub fn build_color(species: ParticleType, time: u32, materials: &Materials, rng: &mut Pcg) -> Self {
+        let material = materials.get(species);
         Particle {
             species,
-            behavior: species.base_behavior(),
-            color: ParticleType::color(species, time),
-            direction_bias: random_bool(0.5),
+            behavior: material.behavior,
+            color: material.color(time, rng),
+            direction_bias: rng.random_bool(0.5),
             awake: true,
             vx: f32::default(),
             vy: f32::default(),
+            temperature: material.temperature,
         }
     }
 
-    pub fn build_color_start_falling(species: ParticleType, time: u32) -> Self {
+    pub fn build_color_start_falling(species: ParticleType, time: u32, materials: &Materials, rng: &mut Pcg) -> Self {
+        let material = materials.get(species);
         Particle {
             species,
             behavior: Some(Behavior::FreeFall(FreeFall)),
-            color: ParticleType::color(species, time),
-            direction_bias: random_bool(0.5),
+            color: material.color(time, rng),
+            direction_bias: rng.random_bool(0.5),
             awake: true,
             vx: f32::default(),
             vy: f32::default(),
+            temperature: material.temperature,
+        }
+    }
+
+    /// what reads back from outside the grid. built without touching any rng
+    /// so bounds checks never shift the random sequence
+    pub fn out_of_bounds() -> Self {
+        Particle {
+            species: ParticleType::OUT_OF_BOUNDS,
+            behavior: Some(Behavior::Solid(Solid)),
+            color: 0xff00ffff,
+            direction_bias: false,
+            awake: true,
+            vx: f32::default(),
+            vy: f32::default(),
+            temperature: AMBIENT_TEMPERATURE,
         }
     }
 
@@ -61,7 +88,7 @@ impl Particle {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.species == ParticleType::Empty
+        self.species == ParticleType::EMPTY
     }
 
     pub fn is_falling(&self) -> bool {
@@ -85,6 +112,13 @@ impl Particle {
         false
     }
 
+    pub fn is_burning(&self) -> bool {
+        if let Some(behavior) = self.behavior {
+            return Behavior::Burning(Burning) == behavior;
+        }
+        false
+    }
+
     pub fn is_gas(&self) -> bool {
         if let Some(behavior) = self.behavior {
             return Behavior::Gas(Gas) == behavior;
@@ -96,50 +130,66 @@ impl Particle {
         self.behavior = Some(Behavior::FreeFall(FreeFall));
     }
 
-    pub fn stop_falling(&mut self) {
-        self.behavior = self.species.base_behavior();
+    pub fn stop_falling(&mut self, materials: &Materials) {
+        self.behavior = materials.get(self.species).behavior;
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum ParticleType {
-    Empty,
-    Sand,
-    Water,
-    Stone,
-    Smoke,
-    Gravel,
-    Wood,
-    Oil,
-    OutOfBounds,
-    EnumLength,
-}
+/// index into the material table. the built in materials always sit at the
+/// same index so they can be named here, anything loaded from a materials file
+/// comes after them
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ParticleType(pub u8);
 
 impl ParticleType {
-    pub fn base_behavior(&self) -> Option<Behavior> {
-        match self {
-            Self::Sand => Some(Behavior::Solid(Solid)),
-            Self::Water => Some(Behavior::Liquid(Liquid)),
-            Self::Smoke => Some(Behavior::Gas(Gas)),
-            Self::Gravel => Some(Behavior::Solid(Solid)),
-            Self::Oil => Some(Behavior::Liquid(Liquid)),
-            Self::OutOfBounds => Some(Behavior::Solid(Solid)),
-            _ => None,
-        }
+    pub const EMPTY: Self = ParticleType(0);
+    pub const SAND: Self = ParticleType(1);
+    pub const WATER: Self = ParticleType(2);
+    pub const STONE: Self = ParticleType(3);
+    pub const SMOKE: Self = ParticleType(4);
+    pub const GRAVEL: Self = ParticleType(5);
+    pub const WOOD: Self = ParticleType(6);
+    pub const OIL: Self = ParticleType(7);
+    pub const STEAM: Self = ParticleType(8);
+    pub const ICE: Self = ParticleType(9);
+    pub const LAVA: Self = ParticleType(10);
+    pub const FIRE: Self = ParticleType(11);
+    pub const ASH: Self = ParticleType(12);
+    pub const ACID: Self = ParticleType(13);
+    pub const ANCHOR: Self = ParticleType(14);
+    pub const SAWDUST: Self = ParticleType(15);
+    pub const CHLORINE: Self = ParticleType(16);
+    /// always the last built in material
+    pub const OUT_OF_BOUNDS: Self = ParticleType(17);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
     }
+}
 
-    fn color(self, time: u32) -> u32 {
-        match self {
-            Self::Empty => color_near(220, 235, 245, 6, 20, time),
-            Self::Sand => color_near(245, 210, 170, 15, 30, time),
-            Self::Water => color_near(180, 225, 235, 15, 30, time),
-            Self::Stone => color_near(190, 190, 200, 15, 35, time),
-            Self::Smoke => color_near(120, 120, 130, 20, 40, time),
-            Self::Gravel => color_near(160, 150, 145, 40, 28, time),
-            Self::Wood => color_near(200, 175, 130, 30, 22, time),
-            Self::Oil => color_near(100, 50, 50, 10, 20, time),
-            Self::OutOfBounds => 0xff00ffff,
-            _ => 0xff000000,
-        }
+/// arguments to `color_near`: the rgb center, how far individual particles
+/// stray from it and the period of the slow shift over time
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorSpec {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub variance: u32,
+    pub time_variance: u32,
+}
+
+impl ColorSpec {
+    pub fn build(red: u8, green: u8, blue: u8, variance: u32, time_variance: u32) -> Self {
+        ColorSpec { red, green, blue, variance, time_variance }
+    }
+
+    pub fn sample(&self, time: u32, rng: &mut Pcg) -> u32 {
+        color_near(rng, self.red, self.green, self.blue, self.variance, self.time_variance, time)
+    }
+
+    /// the center as an opaque 0xAARRGGBB pixel
+    pub fn base(&self) -> u32 {
+        0xff000000 | (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
     }
 }
@@ -0,0 +1,66 @@
+use std::ops::Range;
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+/// pcg32 (xsh-rr variant) from pcg-random.org. tiny, fast, and every stream
+/// is fully determined by its seed and stream id, which is what lets each
+/// chunk get its own reproducible sequence no matter which thread runs it
+#[derive(Clone, Copy, Debug)]
+pub struct Pcg {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg {
+    pub fn build(seed: u64, stream: u64) -> Self {
+        let mut pcg = Pcg { state: 0, increment: (stream << 1) | 1 };
+        pcg.next_u32();
+        pcg.state = pcg.state.wrapping_add(seed);
+        pcg.next_u32();
+        pcg
+    }
+
+    /// raw generator state, enough to resume the exact same sequence later
+    pub fn state(&self) -> (u64, u64) {
+        (self.state, self.increment)
+    }
+
+    pub fn from_state(state: u64, increment: u64) -> Self {
+        Pcg { state, increment: increment | 1 }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rotation = (old >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    pub fn random_bool(&mut self, probability: f64) -> bool {
+        (self.next_u32() as f64) < probability * (u32::MAX as f64 + 1.)
+    }
+
+    /// uniform in [0, 1)
+    pub fn random_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn random_range(&mut self, range: Range<usize>) -> usize {
+        {
+            debug_assert!(range.start < range.end);
+        }
+        range.start + (self.next_u64() % (range.end - range.start) as u64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        (1..items.len()).rev().for_each(|idx| {
+            let other = self.random_range(0..idx + 1);
+            items.swap(idx, other);
+        });
+    }
+}
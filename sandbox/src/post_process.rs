@@ -0,0 +1,331 @@
+use crate::particles::ParticleType;
+use crate::sandbox::SandBox;
+
+/// how far the light of a glowing cell spreads, in cells
+const GLOW_RADIUS: usize = 6;
+const GLOW_STRENGTH: f32 = 1.6;
+
+/// share of the way to white the top cell of a liquid gets pushed
+const HIGHLIGHT: f32 = 0.35;
+
+const OCCLUSION_RADIUS: usize = 5;
+const OCCLUSION_STRENGTH: f32 = 0.3;
+
+/// how much darker the corners get than the middle
+const VIGNETTE: f32 = 0.35;
+
+/// one full screen effect run over the frame after it's been drawn. passes
+/// only ever change the pixels, never the world
+pub trait Pass {
+    fn apply(&self, world: &SandBox, pixels: &mut [u32], scratch: &mut Scratch);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glow;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Highlight;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Occlusion;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vignette;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    Occlusion(Occlusion),
+    Highlight(Highlight),
+    Glow(Glow),
+    Vignette(Vignette),
+}
+
+impl Effect {
+    /// every effect in the order they're applied
+    pub const ALL: [Effect; 4] = [
+        Effect::Occlusion(Occlusion),
+        Effect::Highlight(Highlight),
+        Effect::Glow(Glow),
+        Effect::Vignette(Vignette),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Occlusion(_) => "occlusion",
+            Self::Highlight(_) => "highlight",
+            Self::Glow(_) => "glow",
+            Self::Vignette(_) => "vignette",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<Effect> {
+        Self::ALL.into_iter().find(|effect| effect.name() == name)
+    }
+}
+
+impl Pass for Effect {
+    fn apply(&self, world: &SandBox, pixels: &mut [u32], scratch: &mut Scratch) {
+        match self {
+            Self::Occlusion(occlusion) => occlusion.apply(world, pixels, scratch),
+            Self::Highlight(highlight) => highlight.apply(world, pixels, scratch),
+            Self::Glow(glow) => glow.apply(world, pixels, scratch),
+            Self::Vignette(vignette) => vignette.apply(world, pixels, scratch),
+        }
+    }
+}
+
+/// buffers the passes share between frames so they don't allocate
+#[derive(Default)]
+pub struct Scratch {
+    planes: [Vec<f32>; 3],
+    blur: Blur,
+}
+
+/// the effects stack sitting between the sandbox's frame and the window
+pub struct PostProcess {
+    pub passes: Vec<(Effect, bool)>,
+    pixels: Vec<u32>,
+    scratch: Scratch,
+}
+
+impl PostProcess {
+    /// every pass starts off, whoever draws the frame turns on the ones it
+    /// wants
+    pub fn build() -> Self {
+        PostProcess {
+            passes: Effect::ALL.iter().map(|&effect| (effect, false)).collect(),
+            pixels: Vec::new(),
+            scratch: Scratch::default(),
+        }
+    }
+
+    /// turns an effect on or off, returns whether it's on now
+    pub fn toggle(&mut self, effect: Effect) -> bool {
+        self.passes.iter_mut().filter(|(pass, _)| *pass == effect).fold(false, |_, (_, enabled)| {
+            *enabled = !*enabled;
+            *enabled
+        })
+    }
+
+    pub fn set_all(&mut self, enabled: bool) {
+        self.passes.iter_mut().for_each(|(_, on)| *on = enabled);
+    }
+
+    pub fn set(&mut self, effect: Effect, enabled: bool) {
+        self.passes.iter_mut().filter(|(pass, _)| *pass == effect).for_each(|(_, on)| *on = enabled);
+    }
+
+    /// brings the world's frame up to date and runs every enabled pass over a
+    /// copy of it. with everything off the frame is handed back untouched
+    pub fn apply<'a>(&'a mut self, world: &'a mut SandBox) -> &'a [u32] {
+        world.frame();
+        if self.passes.iter().all(|&(_, enabled)| !enabled) {
+            return &world.frame;
+        }
+        self.pixels.clear();
+        self.pixels.extend_from_slice(&world.frame);
+        self.passes.iter().filter(|(_, enabled)| *enabled).for_each(|(effect, _)| {
+            effect.apply(world, &mut self.pixels, &mut self.scratch);
+        });
+        &self.pixels
+    }
+}
+
+impl Pass for Glow {
+    /// anything with a glow lights up the cells around it in its own color.
+    /// burning cells glow like fire whatever they're made of. only the band of
+    /// rows the light can reach gets blurred
+    fn apply(&self, world: &SandBox, pixels: &mut [u32], scratch: &mut Scratch) {
+        let fire = world.materials.get(ParticleType::FIRE).params.glow;
+        scratch.planes.iter_mut().for_each(|plane| {
+            plane.clear();
+            plane.resize(pixels.len(), 0.);
+        });
+        let mut rows: Option<(usize, usize)> = None;
+        world.grid.iter().enumerate().for_each(|(idx, particle)| {
+            let glow = if particle.is_burning() {
+                fire
+            }
+            else {
+                world.materials.get(particle.species).params.glow
+            };
+            if glow > 0. {
+                let y = idx / world.width;
+                rows = Some(rows.map_or((y, y), |(top, bottom)| (top.min(y), bottom.max(y))));
+                let color = channels(pixels[idx]);
+                (0..3).for_each(|channel| scratch.planes[channel][idx] = color[channel] * glow);
+            }
+        });
+        let Some((top, bottom)) = rows
+        else {
+            return;
+        };
+
+        // two box blurs in a row are close enough to a gaussian, which spreads
+        // the light twice the radius
+        let top = top.saturating_sub(2 * GLOW_RADIUS);
+        let bottom = (bottom + 2 * GLOW_RADIUS + 1).min(world.height);
+        let band = top * world.width..bottom * world.width;
+        scratch.planes.iter_mut().for_each(|plane| {
+            (0..2).for_each(|_| scratch.blur.apply(&mut plane[band.clone()], world.width, bottom - top, GLOW_RADIUS));
+        });
+        pixels[band.clone()].iter_mut().zip(band).for_each(|(pixel, idx)| {
+            let mut color = channels(*pixel);
+            (0..3).for_each(|channel| color[channel] += scratch.planes[channel][idx] * GLOW_STRENGTH);
+            *pixel = pack(color);
+        });
+    }
+}
+
+impl Pass for Highlight {
+    /// the top cell of a resting liquid catches the light
+    fn apply(&self, world: &SandBox, pixels: &mut [u32], _scratch: &mut Scratch) {
+        (world.width..world.grid.len()).for_each(|idx| {
+            if world.grid[idx].is_liquid() && world.grid[idx - world.width].is_empty() {
+                let color = channels(pixels[idx]);
+                pixels[idx] = pack(color.map(|channel| channel + (255. - channel) * HIGHLIGHT));
+            }
+        });
+    }
+}
+
+impl Pass for Occlusion {
+    /// darkens whatever is more than half surrounded by stuff, so the insides
+    /// of piles and air pockets in cavities sink back a little
+    fn apply(&self, world: &SandBox, pixels: &mut [u32], scratch: &mut Scratch) {
+        let plane = &mut scratch.planes[0];
+        plane.clear();
+        plane.extend(world.grid.iter().map(|particle| (!particle.is_empty()) as u8 as f32));
+        scratch.blur.apply(plane, world.width, world.height, OCCLUSION_RADIUS);
+        pixels.iter_mut().zip(plane.iter()).for_each(|(pixel, &covered)| {
+            let shade = 1. - OCCLUSION_STRENGTH * ((covered - 0.5) * 2.).max(0.);
+            *pixel = pack(channels(*pixel).map(|channel| channel * shade));
+        });
+    }
+}
+
+impl Pass for Vignette {
+    /// the darkening grows with the squared distance from the middle, which
+    /// splits into a part from the column and a part from the row
+    fn apply(&self, world: &SandBox, pixels: &mut [u32], _scratch: &mut Scratch) {
+        let (cx, cy) = (world.width as f32 / 2., world.height as f32 / 2.);
+        let corner = cx * cx + cy * cy;
+        let falloff = |distance: f32| VIGNETTE * distance * distance / corner;
+        let columns: Vec<f32> = (0..world.width).map(|x| falloff(x as f32 - cx)).collect();
+        pixels.chunks_mut(world.width).enumerate().for_each(|(y, row)| {
+            let shade = 1. - falloff(y as f32 - cy);
+            row.iter_mut().zip(&columns).for_each(|(pixel, column)| {
+                *pixel = pack(channels(*pixel).map(|channel| channel * (shade - column)));
+            });
+        });
+    }
+}
+
+fn channels(color: u32) -> [f32; 3] {
+    [(color >> 16 & 0xff) as f32, (color >> 8 & 0xff) as f32, (color & 0xff) as f32]
+}
+
+fn pack(color: [f32; 3]) -> u32 {
+    let [red, green, blue] = color.map(|channel| channel.clamp(0., 255.) as u32);
+    (0xff << 24) | (red << 16) | (green << 8) | blue
+}
+
+/// a box blur done one direction at a time with running sums, so the radius
+/// doesn't change the cost. the vertical half walks rows too and keeps a sum
+/// per column, going down the columns one by one thrashes the cache
+#[derive(Default)]
+struct Blur {
+    rows: Vec<f32>,
+    columns: Vec<f32>,
+}
+
+impl Blur {
+    fn apply(&mut self, values: &mut [f32], width: usize, height: usize, radius: usize) {
+        let window = (2 * radius + 1) as f32;
+        self.rows.clear();
+        self.rows.resize(values.len(), 0.);
+        (0..height).for_each(|y| {
+            let row = y * width;
+            let mut sum: f32 = values[row..row + width.min(radius + 1)].iter().sum();
+            (0..width).for_each(|x| {
+                self.rows[row + x] = sum / window;
+                if x + radius + 1 < width {
+                    sum += values[row + x + radius + 1];
+                }
+                if x >= radius {
+                    sum -= values[row + x - radius];
+                }
+            });
+        });
+
+        self.columns.clear();
+        self.columns.resize(width, 0.);
+        (0..height.min(radius + 1)).for_each(|y| {
+            self.columns.iter_mut().zip(&self.rows[y * width..]).for_each(|(sum, value)| *sum += value);
+        });
+        (0..height).for_each(|y| {
+            values[y * width..(y + 1) * width].iter_mut().zip(&self.columns).for_each(|(value, sum)| {
+                *value = sum / window;
+            });
+            if y + radius + 1 < height {
+                let entering = (y + radius + 1) * width;
+                self.columns.iter_mut().zip(&self.rows[entering..]).for_each(|(sum, value)| *sum += value);
+            }
+            if y >= radius {
+                let leaving = (y - radius) * width;
+                self.columns.iter_mut().zip(&self.rows[leaving..]).for_each(|(sum, value)| *sum -= value);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vignette_darkens_towards_the_corners_only() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        (0..30).for_each(|y| (0..40).for_each(|x| world.put_particle(ParticleType::STONE, x, y)));
+        let plain = world.frame().to_vec();
+        let mut effects = PostProcess::build();
+        effects.set(Effect::Vignette(Vignette), true);
+        let shaded = effects.apply(&mut world);
+
+        let brightness = |color: u32| channels(color).iter().sum::<f32>();
+        assert!(shaded[15 * 40 + 20] == plain[15 * 40 + 20]);
+        assert!(shaded.iter().zip(&plain).all(|(&shaded, &plain)| brightness(shaded) <= brightness(plain)));
+        [0, 39, 29 * 40, 30 * 40 - 1].into_iter().for_each(|corner| {
+            assert!(brightness(shaded[corner]) < brightness(plain[corner]));
+        });
+    }
+
+    #[test]
+    fn highlight_lights_only_the_top_of_liquids() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        (20..30).for_each(|y| (0..40).for_each(|x| world.put_particle(ParticleType::WATER, x, y)));
+        (0..100).for_each(|_| world.update());
+        let plain = world.frame().to_vec();
+        let mut effects = PostProcess::build();
+        effects.set(Effect::Highlight(Highlight), true);
+        let lit = effects.apply(&mut world).to_vec();
+
+        let surface = |idx: usize| {
+            idx >= world.width && world.grid[idx].is_liquid() && world.grid[idx - world.width].is_empty()
+        };
+        assert!((0..world.grid.len()).any(surface));
+        (0..world.grid.len()).for_each(|idx| assert!((lit[idx] != plain[idx]) == surface(idx)));
+    }
+
+    #[test]
+    fn with_every_pass_off_the_frame_goes_through() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        (0..40).for_each(|x| world.put_particle(ParticleType::FIRE, x, 10));
+        world.update();
+        let plain = world.frame().to_vec();
+        let mut effects = PostProcess::build();
+        assert!(effects.apply(&mut world) == plain);
+        effects.set_all(true);
+        assert!(effects.apply(&mut world) != plain);
+    }
+}
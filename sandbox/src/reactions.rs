@@ -0,0 +1,104 @@
+use crate::particles::Particle;
+use crate::sandbox::Handler;
+
+const NEIGHBOURS: [(isize, isize); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+impl Handler<'_> {
+    /// looks for a neighbour this particle reacts with and rolls for it. runs
+    /// after the particle has moved, so it reacts with whatever it ends up
+    /// touching this tick
+    pub fn react(&mut self) {
+        let mut sandbox = self.sandbox;
+        let materials = &sandbox.deref().materials;
+        let reactions = &materials.get(self.here.species).reactions;
+        if reactions.is_empty() {
+            return;
+        }
+
+        // start from a random side so no direction is favoured
+        let start = self.rng.random_range(0..NEIGHBOURS.len());
+        let found = (0..NEIGHBOURS.len()).map(|idx| NEIGHBOURS[(start + idx) % NEIGHBOURS.len()]).find_map(|(dx, dy)| {
+            let other = self.get(dx, dy);
+            let reaction = reactions.iter().find(|reaction| reaction.matches(self.here.species, other.species))?;
+            Some((dx, dy, *reaction))
+        });
+        let Some((dx, dy, reaction)) = found
+        else {
+            return;
+        };
+        // a pair that could still react has to be looked at again even if
+        // nothing else around it moves
+        self.keep_active();
+        if !self.rng.random_bool(reaction.probability) {
+            return;
+        }
+
+        let time = self.sandbox.deref().color_shift;
+        let mut product = |species| {
+            let mut particle = Particle::build_color(species, time, materials, self.rng);
+            particle.temperature += reaction.heat;
+            particle
+        };
+        let (here, other) = (product(reaction.into), product(reaction.other_into));
+        *self.get_mut_unchecked(dx, dy) = other;
+        *self.get_mut_here() = here;
+        self.reup_here();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::Materials;
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    fn rule(reactants: &str, products: &str) -> String {
+        format!("[[reaction]]\nreactants = {}\nproducts = {}\nprobability = 1.0\n", reactants, products)
+    }
+
+    /// sand and water that always react when they touch
+    fn sure() -> Materials {
+        let mut materials = Materials::builtin();
+        materials.overlay(&rule("[\"sand\", \"water\"]", "[\"gravel\", \"steam\"]")).expect("valid reaction");
+        materials
+    }
+
+    #[test]
+    fn touching_reactants_turn_into_the_products() {
+        let mut world = SandBox::build_seeded(20, 10, 1);
+        world.set_materials(sure());
+        world.put_particle(ParticleType::SAND, 5, 9);
+        world.put_particle(ParticleType::WATER, 6, 9);
+        (0..3).for_each(|_| world.update());
+        let census = world.census();
+        assert!(census[ParticleType::SAND.index()] == 0 && census[ParticleType::WATER.index()] == 0);
+        assert!(census[ParticleType::GRAVEL.index()] == 1 && census[ParticleType::STEAM.index()] == 1);
+    }
+
+    #[test]
+    fn both_sides_of_a_rule_know_about_it() {
+        let materials = sure();
+        let sand = &materials.get(ParticleType::SAND).reactions;
+        assert!(sand.iter().any(|reaction| reaction.with == Some(ParticleType::WATER)
+            && reaction.into == ParticleType::GRAVEL
+            && reaction.other_into == ParticleType::STEAM));
+        let water = &materials.get(ParticleType::WATER).reactions;
+        assert!(water.iter().any(|reaction| reaction.with == Some(ParticleType::SAND)
+            && reaction.into == ParticleType::STEAM
+            && reaction.other_into == ParticleType::GRAVEL));
+    }
+
+    #[test]
+    fn unknown_names_in_a_reaction_are_errors() {
+        let mut materials = Materials::builtin();
+        let before = materials.get(ParticleType::SAND).reactions.len();
+        [("[\"sand\", \"unobtainium\"]", "[\"gravel\", \"steam\"]"), ("[\"sand\", \"water\"]", "[\"gold\", \"steam\"]")]
+            .into_iter()
+            .for_each(|(reactants, products)| {
+                let err = materials.overlay(&rule(reactants, products)).expect_err("unknown material");
+                assert!(err.to_string().contains("unknown material"));
+            });
+        // nothing was half applied
+        assert!(materials.get(ParticleType::SAND).reactions.len() == before);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+
+use crate::materials::Materials;
+
+/// index that never holds a real color. frames after the first mark every
+/// pixel that didn't change with it so the decoder keeps the old one
+const UNCHANGED: u8 = 255;
+
+/// slots kept back from the material shades for anything drawn on top of the
+/// world, like overlays and text
+const FIXED_COLORS: [u32; 8] =
+    [0xff000000, 0xffffffff, 0xff808080, 0xffff0000, 0xff00ff00, 0xff0000ff, 0xffffff00, 0xff00ffff];
+
+/// 256 color palette for gif frames. every particle color is some material's
+/// center nudged per channel by at most its variance, so instead of a generic
+/// quantizer the palette is a small lattice of shades around each material's
+/// center. soft pastels that a median cut would merge into mud keep their hue
+pub struct Palette {
+    colors: Vec<u32>,
+    lookup: HashMap<u32, u8>,
+}
+
+impl Palette {
+    pub fn from_materials(materials: &Materials) -> Self {
+        let specs: Vec<_> = materials.paintable().filter_map(|(_, material)| material.color).collect();
+        let budget = (UNCHANGED as usize - FIXED_COLORS.len()) / specs.len().max(1);
+        // shades per channel, as many as fit in each material's share
+        let steps = (1..=6).rev().find(|steps| steps * steps * steps <= budget).unwrap_or(1);
+
+        let mut colors = FIXED_COLORS.to_vec();
+        specs.iter().for_each(|spec| {
+            let shade = |base: u8, step: usize| {
+                if steps == 1 {
+                    return base as i32;
+                }
+                let spread = spec.variance as i32;
+                base as i32 - spread + (2 * spread * step as i32) / (steps as i32 - 1)
+            };
+            (0..steps).for_each(|red| {
+                (0..steps).for_each(|green| {
+                    (0..steps).for_each(|blue| {
+                        let channel = |value: i32| value.clamp(0, 255) as u32;
+                        colors.push(
+                            0xff000000
+                                | channel(shade(spec.red, red)) << 16
+                                | channel(shade(spec.green, green)) << 8
+                                | channel(shade(spec.blue, blue)),
+                        );
+                    });
+                });
+            });
+        });
+        colors.truncate(UNCHANGED as usize);
+
+        Palette { colors, lookup: HashMap::new() }
+    }
+
+    pub fn index(&mut self, pixel: u32) -> u8 {
+        let colors = &self.colors;
+        *self.lookup.entry(pixel | 0xff000000).or_insert_with(|| {
+            let distance = |color: u32| {
+                let channel = |shift: u32| ((color >> shift) & 0xff).abs_diff((pixel >> shift) & 0xff).pow(2);
+                channel(16) + channel(8) + channel(0)
+            };
+            (0..colors.len()).min_by_key(|&idx| distance(colors[idx])).unwrap_or_default() as u8
+        })
+    }
+
+    /// always a full 256 entries so the unchanged marker has a slot too
+    fn rgb(&self) -> Vec<u8> {
+        let mut rgb: Vec<u8> =
+            self.colors.iter().flat_map(|&color| [(color >> 16) as u8, (color >> 8) as u8, color as u8]).collect();
+        rgb.resize(256 * 3, 0);
+        rgb
+    }
+}
+
+/// streams frames into an animated gif as they are pushed. most of the world
+/// sits still between frames, so only the pixels that changed are stored and
+/// the rest are left transparent over the previous frame
+pub struct Recorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    palette: Palette,
+    previous: Vec<u8>,
+    width: usize,
+    height: usize,
+    /// hundredths of a second each frame stays up
+    delay: u16,
+    pub frames: usize,
+}
+
+impl Recorder {
+    /// gifs can't be wider or taller than 65535, bigger worlds are an error
+    /// before anything gets written
+    pub fn build(path: &Path, width: usize, height: usize, delay: u16, materials: &Materials) -> Result<Self> {
+        let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height))
+        else {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{}x{} is too big for a gif", width, height)));
+        };
+        let palette = Palette::from_materials(materials);
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, gif_width, gif_height, &palette.rgb()).map_err(Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(Error::other)?;
+        Ok(Recorder { encoder, palette, previous: Vec::new(), width, height, delay, frames: 0 })
+    }
+
+    pub fn push(&mut self, pixels: &[u32]) -> Result<()> {
+        {
+            debug_assert!(pixels.len() == self.width * self.height);
+        }
+        let indices: Vec<u8> = pixels.iter().map(|&pixel| self.palette.index(pixel)).collect();
+        let buffer: Vec<u8> = if self.previous.is_empty() {
+            indices.clone()
+        }
+        else {
+            indices.iter().zip(&self.previous).map(|(&now, &then)| if now == then { UNCHANGED } else { now }).collect()
+        };
+        let frame = gif::Frame {
+            width: self.width as u16,
+            height: self.height as u16,
+            delay: self.delay,
+            dispose: gif::DisposalMethod::Keep,
+            transparent: Some(UNCHANGED),
+            buffer: buffer.into(),
+            ..Default::default()
+        };
+        self.previous = indices;
+        self.encoder.write_frame(&frame).map_err(Error::other)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// writes the trailer and flushes the file
+    pub fn finish(self) -> Result<()> {
+        self.encoder.into_inner().map_err(Error::other)?.into_inner().map_err(|err| err.into_error())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    /// the color a pixel ends up as in the gif
+    fn shade(palette: &mut Palette, pixel: u32) -> u32 {
+        let idx = palette.index(pixel) as usize;
+        palette.colors[idx]
+    }
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("sandbox-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn every_material_gets_shades_near_its_color() {
+        let world = SandBox::build_seeded(4, 4, 1);
+        let mut palette = Palette::from_materials(&world.materials);
+        assert!(palette.colors.len() <= UNCHANGED as usize);
+        // the corners of a material's lattice are its variance away on every
+        // channel, whatever is nearest to the center is no further
+        world.materials.paintable().filter_map(|(_, material)| material.color).for_each(|spec| {
+            let (base, shaded) = (spec.base(), shade(&mut palette, spec.base()));
+            let distance: u32 =
+                [16, 8, 0].iter().map(|shift| ((base >> shift) & 0xff).abs_diff((shaded >> shift) & 0xff).pow(2)).sum();
+            assert!(distance <= 3 * spec.variance.pow(2));
+        });
+        FIXED_COLORS.iter().for_each(|&color| assert!(shade(&mut palette, color) == color));
+    }
+
+    #[test]
+    fn recordings_decode_back_to_the_frames() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        (0..40).for_each(|x| world.put_particle(ParticleType::SAND, x, 5));
+        let path = scratch("recording.gif");
+        let mut recorder = Recorder::build(&path, 40, 30, 2, &world.materials).expect("recorder starts");
+        let mut palette = Palette::from_materials(&world.materials);
+        let mut expected = Vec::new();
+        (0..3).for_each(|_| {
+            let frame = world.frame().to_vec();
+            expected.push(frame.iter().map(|&pixel| shade(&mut palette, pixel)).collect::<Vec<_>>());
+            recorder.push(&frame).expect("frame written");
+            (0..5).for_each(|_| world.update());
+        });
+        recorder.finish().expect("recording finished");
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&path).expect("recording exists")).expect("gif header");
+        let mut screen = vec![0u32; 40 * 30];
+        expected.iter().for_each(|expected| {
+            let frame = decoder.read_next_frame().expect("frame decodes").expect("frame is there");
+            // transparent pixels keep whatever the frame before left there
+            frame.buffer.chunks(4).zip(&mut screen).filter(|(rgba, _)| rgba[3] != 0).for_each(|(rgba, pixel)| {
+                *pixel = 0xff000000 | (rgba[0] as u32) << 16 | (rgba[1] as u32) << 8 | rgba[2] as u32;
+            });
+            assert!(screen == *expected);
+        });
+        assert!(decoder.read_next_frame().expect("trailer decodes").is_none());
+        std::fs::remove_file(&path).expect("recording removed");
+    }
+
+    #[test]
+    fn worlds_too_big_for_a_gif_are_errors() {
+        let world = SandBox::build_seeded(4, 4, 1);
+        let path = scratch("too-big.gif");
+        let Err(err) = Recorder::build(&path, 70_000, 2, 2, &world.materials)
+        else {
+            panic!("a 70000 wide gif got started");
+        };
+        assert!(err.kind() == ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
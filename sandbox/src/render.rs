@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use crate::chunks::Chunk;
+use crate::sandbox::SandBox;
+use crate::thread_ptr::RawPtrMut;
+
+/// below this many changed cells handing the copy to the pool costs more than
+/// just doing it
+const PARALLEL_AREA: usize = 1 << 16;
+
+impl SandBox {
+    /// the color of every cell, kept around between frames. only what changed
+    /// since the last call gets copied over, on the pool when there's enough
+    /// of it. anything written straight into `grid` needs a [`SandBox::redraw`]
+    pub fn frame(&mut self) -> &[u32] {
+        if self.frame.len() != self.grid.len() {
+            self.frame = vec![0; self.grid.len()];
+            self.tiles.redraw_all();
+        }
+        let rects = self.tiles.take_unrendered();
+        let area: usize = rects.iter().map(|rect| (rect.xmax - rect.xmin) * (rect.ymax - rect.ymin)).sum();
+
+        if self.thread_count > 1 && area > PARALLEL_AREA {
+            let threads = self.thread_count;
+            self.pool.resize(threads);
+            let pool = std::mem::take(&mut self.pool);
+            let selfptr = RawPtrMut::build(self as *mut SandBox);
+            let rects = Arc::new(rects);
+            // rects never overlap since each one is cut to its own tile, so
+            // every worker writes a different part of the frame
+            pool.broadcast(Arc::new(move |worker| {
+                let mut sandbox = selfptr;
+                rects.iter().skip(worker).step_by(threads).for_each(|&rect| sandbox.deref().draw(rect));
+            }));
+            self.pool = pool;
+        }
+        else {
+            rects.into_iter().for_each(|rect| self.draw(rect));
+        }
+        &self.frame
+    }
+
+    /// marks the whole frame as out of date
+    pub fn redraw(&mut self) {
+        self.tiles.redraw_all();
+    }
+
+    fn draw(&mut self, rect: Chunk) {
+        (rect.ymin..rect.ymax).for_each(|y| {
+            let (start, end) = (y * self.width + rect.xmin, y * self.width + rect.xmax);
+            self.frame[start..end].iter_mut().zip(&self.grid[start..end]).for_each(|(pixel, particle)| {
+                *pixel = particle.color;
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    /// ticks, paints, clears and undoes, checking the frame against a fresh
+    /// copy of the grid after each
+    fn check_frames(width: usize, height: usize, threads: usize) {
+        let mut world = SandBox::build_seeded(width, height, 5);
+        world.thread_count = threads;
+        world.cluster_size = 20;
+        assert!(world.to_color() == world.frame());
+        (0..8).for_each(|round| {
+            world.add_cluster(ParticleType::SAND, 20 + round * 30, 10);
+            world.add_cluster(ParticleType::WATER, 40 + round * 30, 30);
+            (0..15).for_each(|_| world.update_par());
+            assert!(world.to_color() == world.frame(), "frame is stale after round {}", round);
+        });
+        world.clear();
+        assert!(world.to_color() == world.frame());
+        world.undo().expect("the snapshot still decodes");
+        assert!(world.to_color() == world.frame());
+        (0..15).for_each(|_| world.update_par());
+        assert!(world.to_color() == world.frame());
+    }
+
+    #[test]
+    fn frames_follow_the_grid() {
+        check_frames(300, 120, 1);
+    }
+
+    #[test]
+    fn pooled_frames_follow_the_grid() {
+        // big enough that a full redraw goes to the pool
+        check_frames(400, 300, 4);
+    }
+}
@@ -1,69 +1,124 @@
-use rand::random_range;
-
+use crate::chunks::CHUNK_SIZE;
 use crate::chunks::Chunk;
 use crate::helpers::random_coprime;
+use crate::history::History;
+use crate::materials::Materials;
 use crate::particle_params::ParticleParams;
 use crate::particle_updates::Update;
 use crate::particles::Particle;
 use crate::particles::ParticleType;
+use crate::pcg::Pcg;
+use crate::structure::Structure;
+use crate::thread_pool::ThreadPool;
 use crate::thread_ptr::RawPtrMut;
+use crate::tiles::TileMap;
+use crate::wind::WindField;
 
-pub struct Handler {
+pub struct Handler<'a> {
     pub x: usize,
     pub y: usize,
     pub here: Particle,
-    pub sandbox: RawPtrMut<SandBox>,
+    pub region: Chunk,
+    pub rng: &'a mut Pcg,
+    /// only ever handed out for the length of a tick, while the scheduler
+    /// guarantees nothing else touches the cells in reach
+    pub(crate) sandbox: RawPtrMut<SandBox>,
 }
 
 #[allow(dead_code)]
-impl Handler {
-    pub fn build(x: usize, y: usize, mut sandbox: RawPtrMut<SandBox>) -> Self {
-        Handler { x, y, here: sandbox.deref().get(x, y), sandbox }
+impl<'a> Handler<'a> {
+    pub(crate) fn build(x: usize, y: usize, region: Chunk, rng: &'a mut Pcg, mut sandbox: RawPtrMut<SandBox>) -> Self {
+        Handler { x, y, here: sandbox.deref().get(x, y), region, rng, sandbox }
     }
 
     pub fn update(&mut self) {
+        self.conduct();
+        self.change_phase();
+        self.ignite();
+        self.blow();
         if let Some(behavior) = self.here.behavior {
             behavior.update(self);
         }
+        self.react();
     }
 
     pub fn reup_here(&mut self) {
         self.here = self.sandbox.deref().get(self.x, self.y);
     }
 
+    /// anything outside of the region this handler owns reads as out of bounds,
+    /// so particles treat the edge of the region like a wall for this pass
     pub fn get(&mut self, dx: isize, dy: isize) -> Particle {
         let (nx, ny) = self.relative_index(dx, dy);
+        if !self.region.contains(nx, ny) {
+            return Particle::out_of_bounds();
+        }
         self.sandbox.deref().get(nx, ny)
     }
 
     pub fn get_mut(&mut self, dx: isize, dy: isize) -> Option<&mut Particle> {
         let (nx, ny) = self.relative_index(dx, dy);
+        if !self.region.contains(nx, ny) {
+            return None;
+        }
+        self.sandbox.deref().tiles.wake(nx, ny);
         self.sandbox.deref().get_mut(nx, ny)
     }
 
     pub fn get_mut_unchecked(&mut self, dx: isize, dy: isize) -> &mut Particle {
         let (nx, ny) = self.relative_index(dx, dy);
+        {
+            debug_assert!(self.region.contains(nx, ny));
+        }
+        self.sandbox.deref().tiles.wake(nx, ny);
         self.sandbox.deref().get_mut_unchecked(nx, ny)
     }
 
     pub fn get_mut_here(&mut self) -> &mut Particle {
+        self.keep_active();
         self.sandbox.deref().get_mut_unchecked(self.x, self.y)
     }
 
+    /// makes sure this cell gets looked at again next tick even though nothing
+    /// about it changed, for particles that are only waiting on a dice roll
+    pub fn keep_active(&mut self) {
+        self.sandbox.deref().tiles.wake(self.x, self.y);
+    }
+
     pub fn get_params(&mut self, dx: isize, dy: isize) -> ParticleParams {
         let particle = self.get(dx, dy);
-        self.sandbox.deref().particleparams[particle.species as usize]
+        self.sandbox.deref().materials.get(particle.species).params
     }
 
     pub fn get_params_here(&mut self) -> ParticleParams {
-        self.sandbox.deref().particleparams[self.here.species as usize]
+        self.sandbox.deref().materials.get(self.here.species).params
+    }
+
+    /// every behavior goes by the same densities to decide what ends up on top
+    pub fn heavier_than(&mut self, dx: isize, dy: isize) -> bool {
+        self.get_params_here().density > self.get_params(dx, dy).density
+    }
+
+    pub fn lighter_than(&mut self, dx: isize, dy: isize) -> bool {
+        self.get_params_here().density < self.get_params(dx, dy).density
+    }
+
+    /// only liquids and gases ever make way, and only for something denser
+    pub fn sinks_through(&mut self, dx: isize, dy: isize) -> bool {
+        let other = self.get(dx, dy);
+        (other.is_liquid() || other.is_gas()) && self.heavier_than(dx, dy)
     }
 
     pub fn swap(&mut self, tx: isize, ty: isize) {
         let (nx, ny) = self.relative_index(tx, ty);
+        {
+            debug_assert!(self.region.contains(nx, ny));
+        }
         let from = self.sandbox.deref().index(self.x, self.y);
         let to = self.sandbox.deref().index(nx, ny);
         self.sandbox.deref().swap(from, to);
+        self.sandbox.deref().tiles.wake(self.x, self.y);
+        self.sandbox.deref().tiles.wake(nx, ny);
         (self.x, self.y) = (nx, ny);
         self.reup_here();
     }
@@ -79,91 +134,160 @@ pub struct SandBox {
     pub height: usize,
     pub width: usize,
     pub grid: Vec<Particle>,
-    pub particleparams: [ParticleParams; ParticleType::EnumLength as usize],
+    pub materials: Materials,
     pub thread_count: usize,
+    pub chunk_size: usize,
     pub cluster_size: usize,
     pub chunk_offset: i32,
     pub flipflop: isize,
     pub tick: u32,
     pub color_freq: u32,
     pub color_shift: u32,
+    pub seed: u64,
+    pub rng: Pcg,
+    pub wind: WindField,
+    pub history: History,
+    /// times the updates in every tile for the cost overlay. off by default
+    /// since reading the clock that often slows the tick down a little
+    pub profile: bool,
+    pub(crate) tiles: TileMap,
+    pub(crate) structure: Structure,
+    pub(crate) frame: Vec<u32>,
+    pub(crate) pool: ThreadPool,
+    /// the chunks of every pass of the last tick, kept for the overlay
+    pub(crate) phases: [Vec<Chunk>; 4],
 }
 
 impl SandBox {
     pub fn build(width: usize, height: usize) -> Self {
+        Self::build_seeded(width, height, rand::random())
+    }
+
+    /// every random choice in the simulation is drawn from pcg streams derived
+    /// from the seed, so the same seed and the same edits always produce the
+    /// same grid, on one thread or on the pool. it's ready to run straight
+    /// away, one thread per core and a brush of 10
+    pub fn build_seeded(width: usize, height: usize, seed: u64) -> Self {
+        let mut rng = Pcg::build(seed, 0);
+        let materials = Materials::builtin();
+        let tiles = TileMap::build(width, height);
         SandBox {
             height,
             width,
-            grid: (0..width * height).map(|_| Particle::build(ParticleType::Empty)).collect(),
-            particleparams: ParticleParams::base_params_builder(),
-            thread_count: usize::default(),
-            cluster_size: usize::default(),
-            chunk_offset: i32::default(),
+            grid: (0..width * height).map(|_| Particle::build(ParticleType::EMPTY, &materials, &mut rng)).collect(),
+            materials,
+            thread_count: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            chunk_size: CHUNK_SIZE,
+            cluster_size: 10,
+            chunk_offset: CHUNK_SIZE as i32,
             flipflop: 1,
             tick: u32::default(),
-            color_freq: u32::default(),
+            color_freq: 2,
             color_shift: u32::default(),
+            seed,
+            rng,
+            wind: WindField::build(width, height),
+            history: History::build(),
+            profile: false,
+            structure: Structure::build(tiles.len()),
+            tiles,
+            frame: Vec::new(),
+            pool: ThreadPool::default(),
+            phases: Default::default(),
         }
     }
 
-    pub fn update_par(&mut self) {
-        use std::thread::spawn;
-
-        let chunks = Chunk::columnar_chunks(self.height, self.width, self.thread_count, self.chunk_offset);
+    /// runs a tick on the calling thread. walks the exact same chunks in the
+    /// same order as the pool does, so both produce bit-identical grids
+    pub fn update(&mut self) {
+        self.settle_history();
+        let (halo, phases) = self.plan_tick();
+        let (width, height) = (self.width, self.height);
         let selfptr = RawPtrMut::build(self as *mut SandBox);
 
-        // two pass processing to mitigate (never can eliminate) data races at
-        // the chunk borders
-        let mut handles = Vec::new();
-        (0..chunks.len()).step_by(2).map(|idx| chunks[idx]).for_each(|chunk| {
-            handles.push(spawn(move || {
-                Self::process_zig_zag(chunk, selfptr);
-            }));
-        });
-        // first pass join
-        handles.into_iter().for_each(|handle| {
-            handle.join().unwrap();
+        phases.iter().for_each(|phase| {
+            phase.iter().for_each(|&chunk| {
+                Self::process_zig_zag(chunk, chunk.expand(halo, width, height), selfptr);
+            });
         });
+        self.finish_tick();
+    }
 
-        let mut handles = Vec::new();
-        (0..chunks.len()).skip(1).step_by(2).map(|idx| chunks[idx]).for_each(|chunk| {
-            handles.push(spawn(move || {
-                Self::process_zig_zag(chunk, selfptr);
+    pub fn update_par(&mut self) {
+        use std::sync::Arc;
+
+        self.settle_history();
+        let (halo, phases) = self.plan_tick();
+        let (width, height) = (self.width, self.height);
+        let threads = self.thread_count.max(1);
+        self.pool.resize(threads);
+        // the pool is moved out for the tick so nothing borrows it while the
+        // workers are writing through the raw pointer
+        let pool = std::mem::take(&mut self.pool);
+        let selfptr = RawPtrMut::build(self as *mut SandBox);
+
+        // four pass checkerboard processing. every thread only ever touches its
+        // own chunk plus the halo around it, and same-phase chunks are far enough
+        // apart that those regions never overlap, so there are no races at the
+        // chunk borders and mass is conserved exactly. the pool barrier keeps
+        // the passes from overlapping
+        phases.into_iter().for_each(|phase| {
+            pool.broadcast(Arc::new(move |worker| {
+                phase.iter().skip(worker).step_by(threads).for_each(|&chunk| {
+                    Self::process_zig_zag(chunk, chunk.expand(halo, width, height), selfptr);
+                });
             }));
         });
-        // second pass join - testing and this conserves >> 99.9999%
-        // of mass; less than one in a million particle is being raced and
-        // overwritten
-        handles.into_iter().for_each(|handle| {
-            handle.join().unwrap();
-        });
+        self.pool = pool;
+        self.finish_tick();
+    }
+
+    fn plan_tick(&mut self) -> (usize, [Vec<Chunk>; 4]) {
+        let halo = self.halo();
+        let phases = Chunk::checkerboard_chunks(
+            self.height,
+            self.width,
+            self.chunk_size,
+            halo,
+            self.chunk_offset,
+            &mut self.rng,
+        );
+        self.phases.clone_from(&phases);
+        (halo, phases)
+    }
+
+    fn finish_tick(&mut self) {
+        self.blow_wind();
+        self.settle_structures();
+        self.tiles.advance();
         self.flipflop = -self.flipflop;
         self.tick += 1;
-        self.color_shift = self.tick / self.color_freq;
+        self.color_shift = self.tick / self.color_freq.max(1);
+    }
+
+    /// each chunk draws from its own stream keyed on the tick and where the
+    /// chunk starts, so the sequence doesn't depend on which thread picks it up
+    fn chunk_rng(&self, chunk: Chunk) -> Pcg {
+        let stream = ((self.tick as u64) << 32) | (chunk.ymin * self.width + chunk.xmin) as u64;
+        Pcg::build(self.seed, stream)
     }
 
+    /// a fresh copy of every cell's color, [`SandBox::frame`] skips the
+    /// allocation and everything that didn't change
     pub fn to_color(&self) -> Vec<u32> {
         self.grid.iter().map(|ele| ele.color).collect()
     }
 
-    #[allow(dead_code)]
-    pub fn to_debug(&self) -> Vec<u32> {
-        self.grid
-            .iter()
-            .map(|ele| {
-                if ele.awake {
-                    0xff00ffff
-                }
-                else {
-                    0xff000000
-                }
-            })
-            .collect()
+    /// number of cells holding each particle type, indexed by the type
+    pub fn census(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.materials.len()];
+        self.grid.iter().for_each(|particle| counts[particle.species.index()] += 1);
+        counts
     }
 
     pub fn get(&self, x: usize, y: usize) -> Particle {
         if !self.inbounds(x, y) {
-            return Particle::build(ParticleType::OutOfBounds);
+            return Particle::out_of_bounds();
         }
         let index = self.index(x, y);
         {
@@ -199,11 +323,23 @@ impl SandBox {
         {
             debug_assert!(index < self.width * self.height);
         }
-        if self.grid[index].is_empty() || species == ParticleType::Empty {
-            self.grid[index] = Particle::build_color_start_falling(species, self.color_shift);
+        if self.grid[index].is_empty() || species == ParticleType::EMPTY {
+            self.put_particle(species, x, y);
         }
     }
 
+    /// like [`SandBox::add_particle`] but writes over whatever is there
+    pub fn put_particle(&mut self, species: ParticleType, x: usize, y: usize) {
+        if !self.inbounds(x, y) {
+            return;
+        }
+        let index = self.index(x, y);
+        self.history.record_cell(index, self.grid[index]);
+        self.grid[index] =
+            Particle::build_color_start_falling(species, self.color_shift, &self.materials, &mut self.rng);
+        self.tiles.wake(x, y);
+    }
+
     pub fn add_cluster(&mut self, species: ParticleType, x: usize, y: usize) {
         let bounds = (self.cluster_size / 2) as isize;
         (-bounds..=bounds).for_each(|dy| {
@@ -215,16 +351,33 @@ impl SandBox {
         });
     }
 
+    /// empties the world. it goes into the history as one edit, or joins the
+    /// one that's open
     pub fn clear(&mut self) {
+        let open = self.history.is_editing();
+        self.begin_edit();
+        self.snapshot_edit();
         (0..self.height).for_each(|y| {
             (0..self.width).for_each(|x| {
                 let index = self.index(x, y);
                 {
                     debug_assert!(index < self.width * self.height);
                 }
-                self.grid[index] = Particle::build_color(ParticleType::Empty, self.color_shift);
+                self.grid[index] =
+                    Particle::build_color(ParticleType::EMPTY, self.color_shift, &self.materials, &mut self.rng);
             });
         });
+        self.wind.clear();
+        self.tiles.wake_all();
+        if !open {
+            self.end_edit();
+        }
+    }
+
+    /// how far a single particle update can reach from its starting cell
+    fn halo(&self) -> usize {
+        let reach = self.materials.iter().map(|(_, material)| material.params.terminal_velocity.ceil() as usize).max();
+        reach.unwrap_or_default() + 1
     }
 
     fn swap(&mut self, from: usize, to: usize) {
@@ -248,39 +401,56 @@ impl SandBox {
     /// "pretty" ordering is also exactly what you would expect. zig-zag is by far
     /// the fastest but looks very obviously strictly sequential
     #[allow(dead_code)]
-    fn process_pcg(chunk: Chunk, ptr: RawPtrMut<SandBox>) {
+    fn process_pcg(chunk: Chunk, region: Chunk, mut ptr: RawPtrMut<SandBox>) {
         // algorithm is known as pcg rangom pcg-random.org
         // weird random rectangular region coprime iteration from physics stack exchange
+        let mut rng = ptr.deref().chunk_rng(chunk);
         let width = chunk.xmax - chunk.xmin;
         let height = chunk.ymax - chunk.ymin;
         let area = width * height;
-        let offset = random_range(0..area);
-        let step = random_coprime(area);
+        let offset = rng.random_range(0..area);
+        let step = random_coprime(area, &mut rng);
         for index in 0..area {
             let linear_index = (offset + step * index) % area;
 
             let x = (linear_index % width) + chunk.xmin;
             let y = (linear_index / width) + chunk.ymin;
+            if !ptr.deref().tiles.is_active(x, y) {
+                continue;
+            }
 
-            let mut handler = Handler::build(x, y, ptr);
+            let mut handler = Handler::build(x, y, region, &mut rng, ptr);
             handler.update();
         }
     }
 
     #[allow(dead_code)]
     #[allow(unreachable_code)]
-    fn process_zig_zag(chunk: Chunk, ptr: RawPtrMut<SandBox>) {
-        // process bottom to top, skipping rows and zig-zagging on x
+    fn process_zig_zag(chunk: Chunk, region: Chunk, ptr: RawPtrMut<SandBox>) {
+        // process bottom to top, skipping rows and zig-zagging on x. only the
+        // parts of each row inside an active tile are visited
+        let mut sandbox = ptr;
+        let mut rng = sandbox.deref().chunk_rng(chunk);
+        let profile = sandbox.deref().profile;
+        let tiles = &sandbox.deref().tiles;
         (chunk.ymin..chunk.ymax).rev().step_by(2).for_each(|y| {
-            (chunk.xmin..chunk.xmax).for_each(|x| {
-                let mut handler = Handler::build(x, y, ptr);
-                handler.update();
+            tiles.row_spans(chunk, y).for_each(|(xmin, xmax)| {
+                Self::process_span(tiles, profile, xmin, y, || {
+                    (xmin..xmax).for_each(|x| {
+                        let mut handler = Handler::build(x, y, region, &mut rng, ptr);
+                        handler.update();
+                    });
+                });
             });
         });
         (chunk.ymin..chunk.ymax).rev().skip(1).step_by(2).for_each(|y| {
-            (chunk.xmin..chunk.xmax).rev().for_each(|x| {
-                let mut handler = Handler::build(x, y, ptr);
-                handler.update();
+            tiles.row_spans(chunk, y).rev().for_each(|(xmin, xmax)| {
+                Self::process_span(tiles, profile, xmin, y, || {
+                    (xmin..xmax).rev().for_each(|x| {
+                        let mut handler = Handler::build(x, y, region, &mut rng, ptr);
+                        handler.update();
+                    });
+                });
             });
         });
 
@@ -290,13 +460,13 @@ impl SandBox {
         (chunk.ymin..chunk.ymax).rev().for_each(|y| {
             if y % 2 == 0 {
                 (chunk.xmin..chunk.xmax).for_each(|x| {
-                    let mut handler = Handler::build(x, y, ptr);
+                    let mut handler = Handler::build(x, y, region, &mut rng, ptr);
                     handler.update();
                 });
             }
             else {
                 (chunk.xmin..chunk.xmax).rev().for_each(|x| {
-                    let mut handler = Handler::build(x, y, ptr);
+                    let mut handler = Handler::build(x, y, region, &mut rng, ptr);
                     handler.update();
                 });
             }
@@ -305,19 +475,81 @@ impl SandBox {
         panic!("stagger algorithms are mutually exclusive");
     }
 
-    #[allow(dead_code)]
-    fn process_true_random(chunk: Chunk, ptr: RawPtrMut<SandBox>) {
-        use rand::rng;
-        use rand::seq::SliceRandom;
+    /// a span never leaves its tile, so the whole run gets charged to the tile
+    /// it starts in
+    fn process_span(tiles: &TileMap, profile: bool, x: usize, y: usize, span: impl FnOnce()) {
+        if !profile {
+            span();
+            return;
+        }
+        let start = std::time::Instant::now();
+        span();
+        tiles.charge(x, y, start.elapsed().as_nanos() as u64);
+    }
 
+    #[allow(dead_code)]
+    fn process_true_random(chunk: Chunk, region: Chunk, ptr: RawPtrMut<SandBox>) {
         // collects every index first and entirely randomizes the iteration
-        let mut rng = rng();
+        let mut sandbox = ptr;
+        let mut rng = sandbox.deref().chunk_rng(chunk);
         let mut indices: Vec<(usize, usize)> =
             (chunk.ymin..chunk.ymax).flat_map(|y| (chunk.xmin..chunk.xmax).map(move |x| (x, y))).collect();
-        indices.shuffle(&mut rng);
-        indices.iter().for_each(|&(x, y)| {
-            let mut handler = Handler::build(x, y, ptr);
+        rng.shuffle(&mut indices);
+        let tiles = &sandbox.deref().tiles;
+        indices.iter().filter(|&&(x, y)| tiles.is_active(x, y)).for_each(|&(x, y)| {
+            let mut handler = Handler::build(x, y, region, &mut rng, ptr);
             handler.update();
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a small world with a bit of everything that moves in it
+    fn world(threads: usize) -> SandBox {
+        let mut world = SandBox::build_seeded(96, 80, 7);
+        world.thread_count = threads;
+        world.add_cluster(ParticleType::SAND, 20, 10);
+        world.add_cluster(ParticleType::WATER, 50, 10);
+        world.add_cluster(ParticleType::SMOKE, 70, 60);
+        world.add_cluster(ParticleType::LAVA, 40, 40);
+        world
+    }
+
+    #[test]
+    fn same_seed_same_world_on_any_thread_count() {
+        let mut single = world(1);
+        (0..60).for_each(|_| single.update());
+        [1, 3, 8].into_iter().for_each(|threads| {
+            let mut pooled = world(threads);
+            (0..60).for_each(|_| pooled.update_par());
+            assert!(single.encode() == pooled.encode(), "{} threads drifted from the single threaded run", threads);
+        });
+    }
+
+    #[test]
+    fn parallel_ticks_keep_every_particle() {
+        let mut world = SandBox::build_seeded(160, 120, 3);
+        world.thread_count = 8;
+        world.cluster_size = 30;
+        [(ParticleType::SAND, 30), (ParticleType::WATER, 80), (ParticleType::GRAVEL, 130)].into_iter().for_each(
+            |(species, x)| (0..3).for_each(|row| world.add_cluster(species, x, 15 + row * 30)),
+        );
+        let census = world.census();
+        (0..600).for_each(|_| world.update_par());
+        assert!(world.census() == census);
+        // and it all did go somewhere
+        assert!(world.get(30, 15).is_empty());
+    }
+
+    #[test]
+    fn runs_with_the_defaults_it_was_built_with() {
+        let mut world = SandBox::build_seeded(64, 64, 1);
+        world.add_cluster(ParticleType::WATER, 32, 10);
+        world.update();
+        world.update_par();
+        assert!(world.tick == 2);
+    }
+}
@@ -0,0 +1,406 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+
+use crate::chunks::Chunk;
+use crate::particle_updates::Behavior;
+use crate::particle_updates::Burning;
+use crate::particle_updates::FreeFall;
+use crate::particle_updates::Gas;
+use crate::particle_updates::Liquid;
+use crate::particle_updates::Solid;
+use crate::particles::Particle;
+use crate::particles::ParticleType;
+use crate::pcg::Pcg;
+use crate::sandbox::SandBox;
+use crate::structure::Structure;
+use crate::tiles::TileMap;
+use crate::wind::WindField;
+
+const MAGIC: &[u8; 4] = b"SBOX";
+const VERSION: u16 = 3;
+/// the biggest world a save or level image may describe, four times a 3440
+/// by 2560 screen. checked before anything gets allocated, so a broken header
+/// is an error rather than running out of memory
+pub(crate) const MAX_SIDE: usize = 1 << 16;
+pub(crate) const MAX_AREA: usize = 1 << 25;
+
+/// file layout, all integers little endian:
+///
+/// ```text
+/// magic "SBOX", version u16, species layout fingerprint u64
+/// width u32, height u32, tick u32, flipflop i8, seed u64, rng state u64 u64
+/// one run-length encoded plane per particle field, in grid order:
+///     species u8, behavior u8, awake u8, vx f32, vy f32, color u32, temperature f32
+/// direction bias packed 8 cells to a byte
+/// tile count u32, then the active and pending rect of every tile
+///     each as a present u8 followed by xmin, xmax, ymin, ymax u32 when present
+/// one plane per air cell field: vx f32, vy f32, fan present u8 and fan vx, vy f32
+/// calm u8
+/// ```
+///
+/// every plane is a list of (varint run length, value) pairs. storing each
+/// field on its own keeps the long runs of empty cells intact even though
+/// every cell has a slightly different color. direction bias is a coin flip
+/// per cell so it would never form runs, it's just bit packed instead
+impl SandBox {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.encode())
+    }
+
+    /// replaces the world with the one in the file. runtime settings like the
+    /// thread count and brush size are kept, and loading goes into the undo
+    /// history like clearing does
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let bytes = std::fs::read(path)?;
+        let open = self.history.is_editing();
+        self.begin_edit();
+        self.snapshot_edit();
+        let loaded = self.decode(&bytes);
+        match &loaded {
+            Ok(()) if !open => self.end_edit(),
+            // the world is left as it was, so there's nothing to undo
+            Err(_) if !open => self.history.drop_edit(),
+            _ => (),
+        }
+        loaded
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.layout_fingerprint().to_le_bytes());
+        out.extend_from_slice(&(self.width as u32).to_le_bytes());
+        out.extend_from_slice(&(self.height as u32).to_le_bytes());
+        out.extend_from_slice(&self.tick.to_le_bytes());
+        out.extend_from_slice(&(self.flipflop as i8).to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        let (state, increment) = self.rng.state();
+        out.extend_from_slice(&state.to_le_bytes());
+        out.extend_from_slice(&increment.to_le_bytes());
+
+        encode_plane(&mut out, self.grid.iter().map(|particle| [particle.species.0]));
+        encode_plane(&mut out, self.grid.iter().map(|particle| [behavior_index(particle.behavior)]));
+        encode_plane(&mut out, self.grid.iter().map(|particle| [particle.awake as u8]));
+        encode_plane(&mut out, self.grid.iter().map(|particle| particle.vx.to_le_bytes()));
+        encode_plane(&mut out, self.grid.iter().map(|particle| particle.vy.to_le_bytes()));
+        encode_plane(&mut out, self.grid.iter().map(|particle| particle.color.to_le_bytes()));
+        encode_plane(&mut out, self.grid.iter().map(|particle| particle.temperature.to_le_bytes()));
+        self.grid.chunks(8).for_each(|cells| {
+            out.push(cells.iter().enumerate().fold(0, |bits, (idx, cell)| bits | (cell.direction_bias as u8) << idx));
+        });
+
+        let rects = self.tiles.rects();
+        out.extend_from_slice(&(rects.len() as u32).to_le_bytes());
+        rects.iter().flat_map(|&(current, next)| [current, next]).for_each(|rect| match rect {
+            Some(rect) => {
+                out.push(1);
+                [rect.xmin, rect.xmax, rect.ymin, rect.ymax].iter().for_each(|&bound| {
+                    out.extend_from_slice(&(bound as u32).to_le_bytes());
+                });
+            }
+            None => out.push(0),
+        });
+
+        let wind = &self.wind;
+        encode_plane(&mut out, wind.vx.iter().map(|vx| vx.to_le_bytes()));
+        encode_plane(&mut out, wind.vy.iter().map(|vy| vy.to_le_bytes()));
+        encode_plane(&mut out, wind.fans.iter().map(|fan| [fan.is_some() as u8]));
+        encode_plane(&mut out, wind.fans.iter().map(|fan| fan.unwrap_or_default().0.to_le_bytes()));
+        encode_plane(&mut out, wind.fans.iter().map(|fan| fan.unwrap_or_default().1.to_le_bytes()));
+        out.push(wind.calm as u8);
+        out
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<()> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take::<4>()? != *MAGIC {
+            return Err(invalid("not a sandbox world file"));
+        }
+        let version = u16::from_le_bytes(reader.take()?);
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported world file version {}", version)));
+        }
+        if u64::from_le_bytes(reader.take()?) != self.layout_fingerprint() {
+            return Err(invalid("world file was saved with a different set of materials"));
+        }
+        let width = u32::from_le_bytes(reader.take()?) as usize;
+        let height = u32::from_le_bytes(reader.take()?) as usize;
+        let tick = u32::from_le_bytes(reader.take()?);
+        let flipflop = i8::from_le_bytes(reader.take()?) as isize;
+        let seed = u64::from_le_bytes(reader.take()?);
+        let state = u64::from_le_bytes(reader.take()?);
+        let increment = u64::from_le_bytes(reader.take()?);
+
+        let area = width.checked_mul(height).filter(|&area| area <= MAX_AREA);
+        let Some(area) = area.filter(|_| width > 0 && height > 0 && width <= MAX_SIDE && height <= MAX_SIDE)
+        else {
+            return Err(invalid(&format!("world size {}x{} is out of range", width, height)));
+        };
+        let species = decode_plane::<1>(&mut reader, area)?;
+        let behaviors = decode_plane::<1>(&mut reader, area)?;
+        let awake = decode_plane::<1>(&mut reader, area)?;
+        let vxs = decode_plane::<4>(&mut reader, area)?;
+        let vys = decode_plane::<4>(&mut reader, area)?;
+        let colors = decode_plane::<4>(&mut reader, area)?;
+        let temperatures = decode_plane::<4>(&mut reader, area)?;
+        let biases = (0..area.div_ceil(8)).map(|_| reader.take::<1>()).collect::<Result<Vec<[u8; 1]>>>()?;
+        let mut tiles = TileMap::build(width, height);
+        let tile_count = u32::from_le_bytes(reader.take()?) as usize;
+        if tile_count != tiles.rects().len() {
+            return Err(invalid("tile layout doesn't match the world size"));
+        }
+        let rects = (0..tile_count)
+            .map(|_| Ok((read_rect(&mut reader)?, read_rect(&mut reader)?)))
+            .collect::<Result<Vec<(Option<Chunk>, Option<Chunk>)>>>()?;
+        let inside = |idx: usize, rect: Option<Chunk>| {
+            let bounds = tiles.bounds(idx);
+            rect.is_none_or(|rect| {
+                bounds.xmin <= rect.xmin
+                    && rect.xmin < rect.xmax
+                    && rect.xmax <= bounds.xmax
+                    && bounds.ymin <= rect.ymin
+                    && rect.ymin < rect.ymax
+                    && rect.ymax <= bounds.ymax
+            })
+        };
+        if !rects.iter().enumerate().all(|(idx, &(current, next))| inside(idx, current) && inside(idx, next)) {
+            return Err(invalid("tile rect outside of its tile"));
+        }
+        tiles.restore(&rects);
+        tiles.redraw_all();
+
+        let mut wind = WindField::build(width, height);
+        let cells = wind.width * wind.height;
+        let air_xs = decode_plane::<4>(&mut reader, cells)?;
+        let air_ys = decode_plane::<4>(&mut reader, cells)?;
+        let fans = decode_plane::<1>(&mut reader, cells)?;
+        let fan_xs = decode_plane::<4>(&mut reader, cells)?;
+        let fan_ys = decode_plane::<4>(&mut reader, cells)?;
+        wind.vx = air_xs.into_iter().map(f32::from_le_bytes).collect();
+        wind.vy = air_ys.into_iter().map(f32::from_le_bytes).collect();
+        wind.fans = (0..cells)
+            .map(|idx| (fans[idx][0] != 0).then(|| (f32::from_le_bytes(fan_xs[idx]), f32::from_le_bytes(fan_ys[idx]))))
+            .collect();
+        wind.calm = reader.take::<1>()? != [0];
+
+        let grid = (0..area)
+            .map(|idx| {
+                if species[idx][0] as usize >= self.materials.len() {
+                    return Err(invalid("unknown particle type"));
+                }
+                Ok(Particle {
+                    species: ParticleType(species[idx][0]),
+                    behavior: behavior_from_index(behaviors[idx][0])?,
+                    color: u32::from_le_bytes(colors[idx]),
+                    direction_bias: biases[idx / 8][0] >> (idx % 8) & 1 != 0,
+                    awake: awake[idx][0] != 0,
+                    vx: f32::from_le_bytes(vxs[idx]),
+                    vy: f32::from_le_bytes(vys[idx]),
+                    temperature: f32::from_le_bytes(temperatures[idx]),
+                })
+            })
+            .collect::<Result<Vec<Particle>>>()?;
+
+        self.width = width;
+        self.height = height;
+        self.grid = grid;
+        self.tick = tick;
+        self.flipflop = flipflop;
+        self.seed = seed;
+        self.rng = Pcg::from_state(state, increment);
+        self.color_shift = self.tick / self.color_freq.max(1);
+        // a saved world was already settled when it was written
+        self.structure = Structure::build(tiles.len());
+        self.structure.stale = true;
+        self.wind = wind;
+        self.tiles = tiles;
+        Ok(())
+    }
+
+    /// changes whenever materials are added, removed or reordered, since the
+    /// species plane stores raw material indices
+    fn layout_fingerprint(&self) -> u64 {
+        // fnv-1a
+        self.materials
+            .iter()
+            .flat_map(|(_, material)| material.name.bytes().chain([0]))
+            .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+}
+
+fn read_rect(reader: &mut Reader) -> Result<Option<Chunk>> {
+    if reader.take::<1>()? == [0] {
+        return Ok(None);
+    }
+    let mut bound = || Ok::<usize, Error>(u32::from_le_bytes(reader.take()?) as usize);
+    Ok(Some(Chunk::build(bound()?, bound()?, bound()?, bound()?)))
+}
+
+fn behavior_index(behavior: Option<Behavior>) -> u8 {
+    match behavior {
+        None => 0,
+        Some(Behavior::Solid(_)) => 1,
+        Some(Behavior::Liquid(_)) => 2,
+        Some(Behavior::Gas(_)) => 3,
+        Some(Behavior::FreeFall(_)) => 4,
+        Some(Behavior::Burning(_)) => 5,
+    }
+}
+
+fn behavior_from_index(index: u8) -> Result<Option<Behavior>> {
+    match index {
+        0 => Ok(None),
+        1 => Ok(Some(Behavior::Solid(Solid))),
+        2 => Ok(Some(Behavior::Liquid(Liquid))),
+        3 => Ok(Some(Behavior::Gas(Gas))),
+        4 => Ok(Some(Behavior::FreeFall(FreeFall))),
+        5 => Ok(Some(Behavior::Burning(Burning))),
+        _ => Err(invalid("unknown particle behavior")),
+    }
+}
+
+fn encode_plane<const N: usize>(out: &mut Vec<u8>, mut values: impl Iterator<Item = [u8; N]>) {
+    let Some(mut current) = values.next()
+    else {
+        return;
+    };
+    let mut run = 1;
+    values.for_each(|value| {
+        if value == current {
+            run += 1;
+        }
+        else {
+            write_varint(out, run);
+            out.extend_from_slice(&current);
+            (current, run) = (value, 1);
+        }
+    });
+    write_varint(out, run);
+    out.extend_from_slice(&current);
+}
+
+/// only reserves what the bytes left could possibly hold up front, anything
+/// past that grows with the runs actually read
+fn decode_plane<const N: usize>(reader: &mut Reader, count: usize) -> Result<Vec<[u8; N]>> {
+    let mut values = Vec::with_capacity(count.min(reader.remaining()));
+    while values.len() < count {
+        let run = reader.varint()? as usize;
+        let value = reader.take::<N>()?;
+        if run == 0 || values.len() + run > count {
+            return Err(invalid("corrupt run length"));
+        }
+        values.extend(std::iter::repeat_n(value, run));
+    }
+    Ok(values)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.position)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let end = self.position + N;
+        let bytes = self.bytes.get(self.position..end).ok_or_else(|| invalid("world file is truncated"))?;
+        self.position = end;
+        Ok(bytes.try_into().expect("slice has exactly N bytes"))
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let [byte] = self.take::<1>()?;
+            if shift >= 64 {
+                return Err(invalid("corrupt run length"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> SandBox {
+        let mut world = SandBox::build_seeded(70, 50, 3);
+        world.add_cluster(ParticleType::SAND, 20, 10);
+        world.add_cluster(ParticleType::WATER, 45, 20);
+        world.add_fan(30, 30, 2., 0.);
+        (0..20).for_each(|_| world.update());
+        world
+    }
+
+    #[test]
+    fn round_trip_keeps_everything() {
+        let saved = world();
+        let bytes = saved.encode();
+        let mut loaded = SandBox::build_seeded(8, 8, 0);
+        loaded.decode(&bytes).expect("failed to decode a fresh save");
+        assert!(loaded.encode() == bytes);
+
+        // both carry on exactly the same way
+        let (mut saved, mut loaded) = (saved, loaded);
+        (0..20).for_each(|_| {
+            saved.update();
+            loaded.update();
+        });
+        assert!(saved.encode() == loaded.encode());
+    }
+
+    #[test]
+    fn corrupt_files_are_errors() {
+        let bytes = world().encode();
+        let mut target = SandBox::build_seeded(8, 8, 0);
+        assert!(target.decode(&bytes[..bytes.len() / 2]).is_err());
+        assert!(target.decode(b"nope").is_err());
+
+        // width and height sit right after the magic, version and fingerprint
+        let mut huge = bytes.clone();
+        huge[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        huge[18..22].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(target.decode(&huge).is_err());
+        let mut empty = bytes.clone();
+        empty[14..18].copy_from_slice(&0u32.to_le_bytes());
+        assert!(target.decode(&empty).is_err());
+
+        // a run longer than the plane at the start of the species plane
+        let mut runs = bytes;
+        let plane = 14 + 4 * 3 + 1 + 8 * 3;
+        runs[plane..plane + 3].copy_from_slice(&[0xff, 0xff, 0x7f]);
+        assert!(target.decode(&runs).is_err());
+
+        // a tile rect reaching past its tile
+        let mut world = world();
+        let mut rects = world.tiles.rects();
+        rects[0].0 = Some(Chunk::build(0, 5000, 0, 5000));
+        world.tiles.restore(&rects);
+        assert!(target.decode(&world.encode()).is_err());
+        assert!(target.width == 8 && target.height == 8);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::chunks::Chunk;
+use crate::particles::Particle;
+use crate::sandbox::SandBox;
+
+/// keeps track of the static cells (stone, wood and anything else whose
+/// material never moves on its own) so unsupported bodies can be found without
+/// flood filling the whole grid every tick. each tile keeps a fingerprint of
+/// which of its cells are static, and only the bodies around tiles whose
+/// fingerprint changed get searched
+pub struct Structure {
+    fingerprints: Vec<u64>,
+    /// the grid was replaced wholesale, by loading or importing a level. the
+    /// next pass only takes new fingerprints, so whatever was authored stays
+    /// where it was put until something in it changes
+    pub(crate) stale: bool,
+    /// which search last reached every cell. searches are numbered on from
+    /// one pass to the next, so anything at or past the first search of this
+    /// pass was reached this pass
+    marks: Vec<u32>,
+    next_search: u32,
+    stack: Vec<usize>,
+    body: Vec<usize>,
+}
+
+impl Structure {
+    pub fn build(tiles: usize) -> Self {
+        Structure {
+            fingerprints: vec![0; tiles],
+            stale: false,
+            marks: Vec::new(),
+            next_search: 1,
+            stack: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+impl SandBox {
+    /// a static cell is part of a body when its material doesn't move by
+    /// itself and it isn't currently falling. burning wood still holds up
+    /// whatever is on it
+    pub fn is_static(&self, particle: &Particle) -> bool {
+        !particle.is_empty() && !particle.is_falling() && self.materials.get(particle.species).behavior.is_none()
+    }
+
+    /// breaks every body that has no path of static cells down to the floor
+    /// or to an anchor into falling debris. called at the end of every tick,
+    /// only bodies next to a tile whose static cells changed are looked at
+    pub(crate) fn settle_structures(&mut self) {
+        let stale = self.structure.stale;
+        let tiles: Vec<(usize, Chunk)> = if stale {
+            (0..self.structure.fingerprints.len()).map(|idx| (idx, self.tiles.bounds(idx))).collect()
+        }
+        else {
+            self.tiles.touched().collect()
+        };
+        let changed: Vec<Chunk> = tiles
+            .into_iter()
+            .filter_map(|(idx, bounds)| {
+                let fingerprint = self.fingerprint(bounds);
+                let changed = fingerprint != self.structure.fingerprints[idx];
+                self.structure.fingerprints[idx] = fingerprint;
+                changed.then_some(bounds)
+            })
+            .collect();
+        self.structure.stale = false;
+        if stale || changed.is_empty() {
+            return;
+        }
+
+        // a pass never runs more searches than there are cells
+        let searches = u32::try_from(self.grid.len()).unwrap_or(u32::MAX);
+        if self.structure.marks.len() != self.grid.len() || self.structure.next_search > u32::MAX - searches {
+            self.structure.marks = vec![0; self.grid.len()];
+            self.structure.next_search = 1;
+        }
+        let first = self.structure.next_search;
+        // one cell past the tile catches the bodies that lost a neighbour
+        // across the tile edge
+        changed.into_iter().for_each(|bounds| {
+            let area = bounds.expand(1, self.width, self.height);
+            (area.ymin..area.ymax).for_each(|y| {
+                (area.xmin..area.xmax).for_each(|x| self.settle_body(y * self.width + x, first));
+            });
+        });
+    }
+
+    fn fingerprint(&self, bounds: Chunk) -> u64 {
+        (bounds.ymin..bounds.ymax)
+            .flat_map(|y| (bounds.xmin..bounds.xmax).map(move |x| y * self.width + x))
+            .filter(|&idx| self.is_static(&self.grid[idx]))
+            .fold(0u64, |hash, idx| hash.wrapping_add((idx as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15)))
+    }
+
+    /// walks the body the cell belongs to until it finds the floor, an anchor
+    /// or a cell an earlier search this pass already found held up. trying
+    /// downwards first usually gets there in a few steps. a body that runs
+    /// out of cells first has nothing under it and crumbles
+    fn settle_body(&mut self, start: usize, first: u32) {
+        if self.structure.marks[start] >= first || !self.is_static(&self.grid[start]) {
+            return;
+        }
+        let (width, height) = (self.width, self.height);
+        let search = self.structure.next_search;
+        self.structure.next_search += 1;
+        let mut marks = std::mem::take(&mut self.structure.marks);
+        let mut stack = std::mem::take(&mut self.structure.stack);
+        let mut body = std::mem::take(&mut self.structure.body);
+        stack.clear();
+        body.clear();
+        marks[start] = search;
+        stack.push(start);
+
+        let mut supported = false;
+        while let Some(idx) = stack.pop() {
+            body.push(idx);
+            let (x, y) = (idx % width, idx / width);
+            if y == height - 1 || self.materials.get(self.grid[idx].species).anchor {
+                supported = true;
+                break;
+            }
+            // the last one pushed is the first one tried
+            supported = [(x, y.wrapping_sub(1)), (x.wrapping_sub(1), y), (x + 1, y), (x, y + 1)]
+                .into_iter()
+                .filter(|&(nx, ny)| nx < width && ny < height)
+                .map(|(nx, ny)| ny * width + nx)
+                .filter(|&next| self.is_static(&self.grid[next]))
+                .any(|next| {
+                    if marks[next] == search {
+                        return false;
+                    }
+                    if marks[next] >= first {
+                        return true;
+                    }
+                    marks[next] = search;
+                    stack.push(next);
+                    false
+                });
+            if supported {
+                break;
+            }
+        }
+
+        if !supported {
+            body.iter().for_each(|&idx| {
+                let particle = self.grid[idx];
+                let Some(debris) = self.materials.get(particle.species).crumbles_into
+                else {
+                    return;
+                };
+                let mut falling =
+                    Particle::build_color_start_falling(debris, self.color_shift, &self.materials, &mut self.rng);
+                falling.temperature = particle.temperature;
+                self.grid[idx] = falling;
+                self.tiles.wake(idx % width, idx / width);
+            });
+        }
+        self.structure.marks = marks;
+        self.structure.stack = stack;
+        self.structure.body = body;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::Image;
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    fn count(world: &SandBox, species: ParticleType) -> usize {
+        world.grid.iter().filter(|particle| particle.species == species).count()
+    }
+
+    fn wall(world: &mut SandBox, species: ParticleType, x: usize, ys: std::ops::Range<usize>) {
+        ys.for_each(|y| world.put_particle(species, x, y));
+    }
+
+    #[test]
+    fn bodies_on_the_floor_hold() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        wall(&mut world, ParticleType::STONE, 10, 10..30);
+        (0..5).for_each(|_| world.update());
+        assert!(count(&world, ParticleType::STONE) == 20);
+    }
+
+    #[test]
+    fn painted_bodies_in_the_air_crumble() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        wall(&mut world, ParticleType::STONE, 10, 5..10);
+        // painted cells fall for a tick before they count as static
+        (0..3).for_each(|_| world.update());
+        assert!(count(&world, ParticleType::STONE) == 0);
+        assert!(count(&world, ParticleType::GRAVEL) == 5);
+    }
+
+    #[test]
+    fn cutting_a_body_loose_drops_only_the_loose_part() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        wall(&mut world, ParticleType::STONE, 10, 0..30);
+        (20..30).for_each(|x| world.put_particle(ParticleType::STONE, x, 29));
+        world.update();
+        world.put_particle(ParticleType::EMPTY, 10, 15);
+        world.update();
+        // everything above the gap drops, the part below it and the floor stay
+        assert!(count(&world, ParticleType::STONE) == 14 + 10);
+        assert!(count(&world, ParticleType::GRAVEL) == 15);
+    }
+
+    #[test]
+    fn anchors_hold_bodies_up() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        world.put_particle(ParticleType::ANCHOR, 10, 4);
+        wall(&mut world, ParticleType::STONE, 10, 5..10);
+        (0..5).for_each(|_| world.update());
+        assert!(count(&world, ParticleType::STONE) == 5);
+    }
+
+    #[test]
+    fn imported_levels_stay_as_drawn() {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        let stone = world.materials.get(ParticleType::STONE).color.expect("stone has a color").base();
+        let mut pixels = vec![0; 40 * 30];
+        (5..10).for_each(|y| pixels[y * 40 + 10] = stone);
+        world.import_level(&Image { width: 40, height: 30, pixels }, None);
+        (0..5).for_each(|_| world.update());
+        assert!(count(&world, ParticleType::STONE) == 5);
+
+        // until something in it changes
+        world.put_particle(ParticleType::EMPTY, 10, 9);
+        world.update();
+        assert!(count(&world, ParticleType::STONE) == 0);
+    }
+}
@@ -0,0 +1,97 @@
+use std::panic::AssertUnwindSafe;
+use std::panic::catch_unwind;
+use std::sync::Arc;
+use std::sync::Barrier;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::channel;
+use std::thread::JoinHandle;
+use std::thread::spawn;
+
+/// a job gets handed the index of the worker running it so it can pick its
+/// share of the work
+pub type Job = Arc<dyn Fn(usize) + Send + Sync>;
+
+enum Message {
+    Run(Job),
+    Shutdown,
+}
+
+struct Worker {
+    sender: Sender<Message>,
+    handle: JoinHandle<()>,
+}
+
+/// long lived workers that all run the same job and then meet at a barrier, so
+/// spinning up os threads isn't paid for every pass of every tick
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    barrier: Arc<Barrier>,
+    panicked: Arc<AtomicBool>,
+}
+
+impl ThreadPool {
+    pub fn build(size: usize) -> Self {
+        let barrier = Arc::new(Barrier::new(size + 1));
+        let panicked = Arc::new(AtomicBool::new(false));
+        let workers = (0..size)
+            .map(|index| {
+                let (sender, receiver) = channel();
+                let barrier = barrier.clone();
+                let panicked = panicked.clone();
+                let handle = spawn(move || {
+                    while let Ok(Message::Run(job)) = receiver.recv() {
+                        // the barrier has to be reached even if the job blows
+                        // up, otherwise the caller would wait forever
+                        if catch_unwind(AssertUnwindSafe(|| job(index))).is_err() {
+                            panicked.store(true, Ordering::Relaxed);
+                        }
+                        barrier.wait();
+                    }
+                });
+                Worker { sender, handle }
+            })
+            .collect();
+
+        ThreadPool { workers, barrier, panicked }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn resize(&mut self, size: usize) {
+        if size != self.size() {
+            *self = Self::build(size);
+        }
+    }
+
+    /// runs the job once on every worker and blocks until all of them finish
+    pub fn broadcast(&self, job: Job) {
+        self.workers.iter().for_each(|worker| {
+            worker.sender.send(Message::Run(job.clone())).expect("worker thread hung up");
+        });
+        self.barrier.wait();
+        if self.panicked.load(Ordering::Relaxed) {
+            panic!("worker thread panicked");
+        }
+    }
+}
+
+impl Default for ThreadPool {
+    fn default() -> Self {
+        Self::build(0)
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.workers.iter().for_each(|worker| {
+            let _ = worker.sender.send(Message::Shutdown);
+        });
+        self.workers.drain(..).for_each(|worker| {
+            let _ = worker.handle.join();
+        });
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use crate::chunks::Chunk;
+
+pub const TILE_SIZE: usize = 32;
+
+/// bounding box of everything that changed inside a tile. it only ever grows
+/// through atomic min/max, so threads working on neighbouring chunks can both
+/// mark the same tile without racing
+struct DirtyRect {
+    xmin: AtomicUsize,
+    xmax: AtomicUsize,
+    ymin: AtomicUsize,
+    ymax: AtomicUsize,
+}
+
+impl DirtyRect {
+    fn build() -> Self {
+        DirtyRect {
+            xmin: AtomicUsize::new(usize::MAX),
+            xmax: AtomicUsize::new(0),
+            ymin: AtomicUsize::new(usize::MAX),
+            ymax: AtomicUsize::new(0),
+        }
+    }
+
+    fn grow(&self, area: Chunk) {
+        self.xmin.fetch_min(area.xmin, Ordering::Relaxed);
+        self.xmax.fetch_max(area.xmax, Ordering::Relaxed);
+        self.ymin.fetch_min(area.ymin, Ordering::Relaxed);
+        self.ymax.fetch_max(area.ymax, Ordering::Relaxed);
+    }
+
+    fn peek(&self) -> Option<Chunk> {
+        let xmin = self.xmin.load(Ordering::Relaxed);
+        let xmax = self.xmax.load(Ordering::Relaxed);
+        let ymin = self.ymin.load(Ordering::Relaxed);
+        let ymax = self.ymax.load(Ordering::Relaxed);
+        if xmin >= xmax || ymin >= ymax {
+            return None;
+        }
+        Some(Chunk::build(xmin, xmax, ymin, ymax))
+    }
+
+    fn take(&self) -> Option<Chunk> {
+        let xmin = self.xmin.swap(usize::MAX, Ordering::Relaxed);
+        let xmax = self.xmax.swap(0, Ordering::Relaxed);
+        let ymin = self.ymin.swap(usize::MAX, Ordering::Relaxed);
+        let ymax = self.ymax.swap(0, Ordering::Relaxed);
+        if xmin >= xmax || ymin >= ymax {
+            return None;
+        }
+        Some(Chunk::build(xmin, xmax, ymin, ymax))
+    }
+}
+
+struct Tile {
+    current: Option<Chunk>,
+    next: DirtyRect,
+    /// everything that changed since the frame was last drawn
+    unrendered: Option<Chunk>,
+    /// nanoseconds spent updating the tile, only counted while profiling
+    cost: AtomicU64,
+    spent: u64,
+}
+
+/// fixed grid of tiles laid over the sandbox. each tile remembers which part
+/// of it needs processing this tick and collects what was touched for the next
+/// one, so fully settled regions cost nothing
+pub struct TileMap {
+    width: usize,
+    height: usize,
+    columns: usize,
+    tiles: Vec<Tile>,
+}
+
+impl TileMap {
+    pub fn build(width: usize, height: usize) -> Self {
+        let columns = width.div_ceil(TILE_SIZE);
+        let rows = height.div_ceil(TILE_SIZE);
+        let tiles = (0..columns * rows)
+            .map(|_| Tile {
+                current: None,
+                next: DirtyRect::build(),
+                unrendered: None,
+                cost: AtomicU64::new(0),
+                spent: 0,
+            })
+            .collect();
+        let mut tilemap = TileMap { width, height, columns, tiles };
+        tilemap.wake_all();
+        tilemap.redraw_all();
+        tilemap
+    }
+
+    /// marks the cell and its direct neighbours for the next tick, spilling
+    /// into the neighbouring tiles when the cell sits on a tile edge
+    pub fn wake(&self, x: usize, y: usize) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        self.wake_area(Chunk::build(x.saturating_sub(1), x + 2, y.saturating_sub(1), y + 2));
+    }
+
+    pub fn wake_area(&self, area: Chunk) {
+        let area = Chunk::build(area.xmin, area.xmax.min(self.width), area.ymin, area.ymax.min(self.height));
+        if area.xmin >= area.xmax || area.ymin >= area.ymax {
+            return;
+        }
+        (area.ymin / TILE_SIZE..=(area.ymax - 1) / TILE_SIZE).for_each(|row| {
+            (area.xmin / TILE_SIZE..=(area.xmax - 1) / TILE_SIZE).for_each(|col| {
+                let clipped = Chunk::build(
+                    area.xmin.max(col * TILE_SIZE),
+                    area.xmax.min((col + 1) * TILE_SIZE),
+                    area.ymin.max(row * TILE_SIZE),
+                    area.ymax.min((row + 1) * TILE_SIZE),
+                );
+                self.tiles[row * self.columns + col].next.grow(clipped);
+            });
+        });
+    }
+
+    pub fn wake_all(&self) {
+        self.wake_area(Chunk::build(0, self.width, 0, self.height));
+    }
+
+    /// adds to the time spent on the tile holding the cell this tick
+    pub fn charge(&self, x: usize, y: usize, nanos: u64) {
+        self.tiles[(y / TILE_SIZE) * self.columns + x / TILE_SIZE].cost.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// nanoseconds the tile holding the cell took last tick
+    pub fn spent(&self, x: usize, y: usize) -> u64 {
+        self.tiles[(y / TILE_SIZE) * self.columns + x / TILE_SIZE].spent
+    }
+
+    /// promotes everything marked during the tick to be processed next tick
+    pub fn advance(&mut self) {
+        self.tiles.iter_mut().for_each(|tile| {
+            tile.current = tile.next.take();
+            tile.spent = tile.cost.swap(0, Ordering::Relaxed);
+            if let Some(rect) = tile.current {
+                tile.unrendered = Some(tile.unrendered.map_or(rect, |unrendered| unrendered.union(rect)));
+            }
+        });
+    }
+
+    /// everything that may have changed since the last call, one rect per
+    /// tile. that includes whatever was painted since the last tick
+    pub fn take_unrendered(&mut self) -> Vec<Chunk> {
+        self.tiles
+            .iter_mut()
+            .filter_map(|tile| match (tile.unrendered.take(), tile.next.peek()) {
+                (Some(unrendered), Some(next)) => Some(unrendered.union(next)),
+                (unrendered, next) => unrendered.or(next),
+            })
+            .collect()
+    }
+
+    pub fn redraw_all(&mut self) {
+        (0..self.tiles.len()).for_each(|idx| self.tiles[idx].unrendered = Some(self.bounds(idx)));
+    }
+
+    /// the active and pending rect of every tile. which cells get visited
+    /// decides which random rolls happen, so this is part of the world state
+    pub fn rects(&self) -> Vec<(Option<Chunk>, Option<Chunk>)> {
+        self.tiles.iter().map(|tile| (tile.current, tile.next.peek())).collect()
+    }
+
+    pub fn restore(&mut self, rects: &[(Option<Chunk>, Option<Chunk>)]) {
+        {
+            debug_assert!(rects.len() == self.tiles.len());
+        }
+        self.tiles.iter_mut().zip(rects).for_each(|(tile, &(current, next))| {
+            tile.current = current;
+            tile.next.take();
+            if let Some(next) = next {
+                tile.next.grow(next);
+            }
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// the index and full bounds of every tile something was written to this
+    /// tick. nothing changes anywhere else without marking its tile
+    pub fn touched(&self) -> impl Iterator<Item = (usize, Chunk)> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.next.peek().is_some())
+            .map(|(idx, _)| (idx, self.bounds(idx)))
+    }
+
+    /// the cells a tile covers, tiles on the right and bottom edge are cut off
+    /// by the world
+    pub fn bounds(&self, idx: usize) -> Chunk {
+        let (col, row) = (idx % self.columns, idx / self.columns);
+        Chunk::build(
+            col * TILE_SIZE,
+            ((col + 1) * TILE_SIZE).min(self.width),
+            row * TILE_SIZE,
+            ((row + 1) * TILE_SIZE).min(self.height),
+        )
+    }
+
+    pub fn is_active(&self, x: usize, y: usize) -> bool {
+        self.tiles[(y / TILE_SIZE) * self.columns + x / TILE_SIZE]
+            .current
+            .is_some_and(|rect| rect.contains(x, y))
+    }
+
+    /// the horizontal runs of active cells in one row of a chunk, left to right
+    pub fn row_spans(&self, chunk: Chunk, y: usize) -> impl DoubleEndedIterator<Item = (usize, usize)> + '_ {
+        let row = y / TILE_SIZE;
+        (chunk.xmin / TILE_SIZE..=(chunk.xmax - 1) / TILE_SIZE).filter_map(move |col| {
+            let rect = self.tiles[row * self.columns + col].current?;
+            if y < rect.ymin || y >= rect.ymax {
+                return None;
+            }
+            let xmin = rect.xmin.max(chunk.xmin);
+            let xmax = rect.xmax.min(chunk.xmax);
+            (xmin < xmax).then_some((xmin, xmax))
+        })
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+
+use crate::helpers::LineTracer;
+use crate::particles::ParticleType;
+use crate::sandbox::SandBox;
+
+/// share of the cells under a spray brush that get painted every frame
+pub const SPRAY_DENSITY: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Circle,
+    Square,
+    /// scatters paint over the given share of a circle every frame
+    Spray(f64),
+    /// a brush wide line from where the stroke started to where it ended
+    Line,
+    /// fills the rectangle between where the stroke started and ended
+    Rect,
+    /// fills everything connected to the clicked cell that holds the same
+    /// species
+    Fill,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 6] =
+        [Shape::Circle, Shape::Square, Shape::Spray(SPRAY_DENSITY), Shape::Line, Shape::Rect, Shape::Fill];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Circle => "circle",
+            Self::Square => "square",
+            Self::Spray(_) => "spray",
+            Self::Line => "line",
+            Self::Rect => "rect",
+            Self::Fill => "fill",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<Shape> {
+        Self::ALL.into_iter().find(|shape| shape.name() == name)
+    }
+
+    /// the shape after this one, wrapping around
+    pub fn next(&self) -> Shape {
+        let idx = Self::ALL.iter().position(|shape| shape.name() == self.name()).unwrap_or_default();
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    /// paints continuously while the button is held rather than once the
+    /// stroke is done
+    fn is_freehand(&self) -> bool {
+        matches!(self, Self::Circle | Self::Square | Self::Spray(_))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// only fills empty cells
+    Paint,
+    /// only overwrites whatever species was under the cursor when the stroke
+    /// started
+    Replace,
+    /// empties everything the brush covers whatever the material
+    Erase,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Paint, Mode::Replace, Mode::Erase];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Paint => "paint",
+            Self::Replace => "replace",
+            Self::Erase => "erase",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<Mode> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn next(&self) -> Mode {
+        let idx = Self::ALL.iter().position(|mode| mode == self).unwrap_or_default();
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+/// what the mouse does to the world. the brush size is the sandbox's
+/// `cluster_size` so tools and scripts agree on it
+#[derive(Clone, Copy, Debug)]
+pub struct Tool {
+    pub species: ParticleType,
+    pub shape: Shape,
+    pub mode: Mode,
+    /// where the stroke started, only set while the button is down
+    start: Option<(usize, usize)>,
+    last: (usize, usize),
+    /// the only species a replace stroke overwrites
+    target: ParticleType,
+}
+
+impl Tool {
+    pub fn build(species: ParticleType, shape: Shape, mode: Mode) -> Self {
+        Tool { species, shape, mode, start: None, last: (0, 0), target: ParticleType::EMPTY }
+    }
+
+    /// fed the cursor and the button every frame. freehand shapes paint along
+    /// the whole path since the last frame so fast strokes don't break up,
+    /// lines and rectangles go down once the button is let go
+    pub fn stroke(&mut self, world: &mut SandBox, x: usize, y: usize, down: bool) {
+        match (self.start, down) {
+            (None, true) => {
+                self.start = Some((x, y));
+                self.last = (x, y);
+                self.target = world.get(x, y).species;
+                match self.shape {
+                    Shape::Fill => self.fill(world, x, y),
+                    Shape::Line | Shape::Rect => {}
+                    _ => self.stamp(world, x, y),
+                }
+            }
+            (Some(_), true) => {
+                if self.shape.is_freehand() {
+                    self.trace(world, self.last, (x, y));
+                }
+                self.last = (x, y);
+            }
+            (Some(start), false) => {
+                match self.shape {
+                    Shape::Line => self.trace(world, start, (x, y)),
+                    Shape::Rect => self.rect(world, start, (x, y)),
+                    _ => {}
+                }
+                self.start = None;
+            }
+            (None, false) => {}
+        }
+    }
+
+    /// stamps the brush on every cell of the line, spray only goes where the
+    /// cursor actually is so it thins out on fast strokes like a real can
+    fn trace(&self, world: &mut SandBox, from: (usize, usize), to: (usize, usize)) {
+        if let Shape::Spray(_) = self.shape {
+            self.stamp(world, to.0, to.1);
+            return;
+        }
+        let (dx, dy) = (to.0 as f32 - from.0 as f32, to.1 as f32 - from.1 as f32);
+        let mut line = LineTracer::build(from.0 as isize, from.1 as isize, dx, dy);
+        while let Some((x, y)) = line.step() {
+            self.stamp(world, x as usize, y as usize);
+        }
+    }
+
+    fn stamp(&self, world: &mut SandBox, x: usize, y: usize) {
+        let bounds = (world.cluster_size / 2) as isize;
+        (-bounds..=bounds).for_each(|dy| {
+            (-bounds..=bounds).for_each(|dx| {
+                let inside = match self.shape {
+                    Shape::Square => true,
+                    Shape::Spray(density) => dx * dx + dy * dy <= bounds * bounds && world.rng.random_bool(density),
+                    _ => dx * dx + dy * dy <= bounds * bounds,
+                };
+                if inside {
+                    self.put(world, x.saturating_add_signed(dx), y.saturating_add_signed(dy));
+                }
+            });
+        });
+    }
+
+    fn rect(&self, world: &mut SandBox, from: (usize, usize), to: (usize, usize)) {
+        (from.1.min(to.1)..=from.1.max(to.1)).for_each(|y| {
+            (from.0.min(to.0)..=from.0.max(to.0)).for_each(|x| self.put(world, x, y));
+        });
+    }
+
+    /// a four way flood from the clicked cell over everything of the same
+    /// species. erasing a region this way empties it
+    fn fill(&self, world: &mut SandBox, x: usize, y: usize) {
+        let species = match self.mode {
+            Mode::Erase => ParticleType::EMPTY,
+            _ => self.species,
+        };
+        if self.target == species || self.target == ParticleType::OUT_OF_BOUNDS {
+            return;
+        }
+        let mut queue = VecDeque::from([(x, y)]);
+        while let Some((x, y)) = queue.pop_front() {
+            if world.get(x, y).species != self.target {
+                continue;
+            }
+            world.put_particle(species, x, y);
+            [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)]
+                .into_iter()
+                .filter(|&(nx, ny)| world.get(nx, ny).species == self.target)
+                .for_each(|neighbour| queue.push_back(neighbour));
+        }
+    }
+
+    fn put(&self, world: &mut SandBox, x: usize, y: usize) {
+        match self.mode {
+            Mode::Paint => world.add_particle(self.species, x, y),
+            Mode::Replace => {
+                if world.get(x, y).species == self.target && self.target != self.species {
+                    world.put_particle(self.species, x, y);
+                }
+            }
+            Mode::Erase => world.put_particle(ParticleType::EMPTY, x, y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> SandBox {
+        let mut world = SandBox::build_seeded(40, 30, 1);
+        world.cluster_size = 1;
+        world
+    }
+
+    fn count(world: &SandBox, species: ParticleType) -> usize {
+        world.census()[species.index()]
+    }
+
+    /// presses at the first point, drags through the rest and lets go at the
+    /// last one
+    fn drag(tool: &mut Tool, world: &mut SandBox, points: &[(usize, usize)]) {
+        points.iter().for_each(|&(x, y)| tool.stroke(world, x, y, true));
+        let &(x, y) = points.last().expect("at least one point");
+        tool.stroke(world, x, y, false);
+    }
+
+    #[test]
+    fn fast_freehand_strokes_leave_no_gaps() {
+        let mut world = world();
+        let mut tool = Tool::build(ParticleType::STONE, Shape::Square, Mode::Paint);
+        drag(&mut tool, &mut world, &[(2, 5), (30, 5), (30, 20)]);
+        assert!((2..=30).all(|x| world.get(x, 5).species == ParticleType::STONE));
+        assert!((5..=20).all(|y| world.get(30, y).species == ParticleType::STONE));
+        assert!(count(&world, ParticleType::STONE) == 29 + 15);
+    }
+
+    #[test]
+    fn lines_go_down_once_let_go() {
+        let mut world = world();
+        let mut tool = Tool::build(ParticleType::STONE, Shape::Line, Mode::Paint);
+        tool.stroke(&mut world, 2, 2, true);
+        tool.stroke(&mut world, 10, 20, true);
+        tool.stroke(&mut world, 20, 12, true);
+        assert!(count(&world, ParticleType::STONE) == 0);
+        tool.stroke(&mut world, 20, 12, false);
+        // one cell for every step along the longer side, nothing where the
+        // cursor passed on the way
+        assert!(count(&world, ParticleType::STONE) == 19);
+        assert!(world.get(2, 2).species == ParticleType::STONE && world.get(20, 12).species == ParticleType::STONE);
+        assert!(world.get(10, 20).is_empty());
+    }
+
+    #[test]
+    fn rectangles_fill_between_the_corners() {
+        let mut world = world();
+        let mut tool = Tool::build(ParticleType::STONE, Shape::Rect, Mode::Paint);
+        drag(&mut tool, &mut world, &[(7, 9), (3, 4)]);
+        assert!(count(&world, ParticleType::STONE) == 5 * 6);
+        assert!((4..=9).all(|y| (3..=7).all(|x| world.get(x, y).species == ParticleType::STONE)));
+    }
+
+    #[test]
+    fn fills_stop_at_other_species() {
+        let mut world = world();
+        let mut walls = Tool::build(ParticleType::STONE, Shape::Rect, Mode::Paint);
+        drag(&mut walls, &mut world, &[(5, 5), (15, 5)]);
+        drag(&mut walls, &mut world, &[(5, 15), (15, 15)]);
+        drag(&mut walls, &mut world, &[(5, 5), (5, 15)]);
+        drag(&mut walls, &mut world, &[(15, 5), (15, 15)]);
+        let stone = count(&world, ParticleType::STONE);
+
+        let mut fill = Tool::build(ParticleType::WATER, Shape::Fill, Mode::Paint);
+        drag(&mut fill, &mut world, &[(10, 10)]);
+        assert!(count(&world, ParticleType::WATER) == 9 * 9);
+        assert!(world.get(20, 20).is_empty());
+
+        // erasing with a fill takes out the whole connected body
+        let mut erase = Tool::build(ParticleType::WATER, Shape::Fill, Mode::Erase);
+        drag(&mut erase, &mut world, &[(5, 10)]);
+        assert!(count(&world, ParticleType::STONE) == 0);
+        assert!(count(&world, ParticleType::WATER) == 9 * 9);
+        assert!(stone == 4 * 10);
+    }
+
+    #[test]
+    fn modes_decide_what_gets_overwritten() {
+        let mut world = world();
+        let mut sand = Tool::build(ParticleType::SAND, Shape::Rect, Mode::Paint);
+        drag(&mut sand, &mut world, &[(0, 0), (9, 9)]);
+        let mut water = Tool::build(ParticleType::WATER, Shape::Rect, Mode::Paint);
+        drag(&mut water, &mut world, &[(5, 0), (19, 9)]);
+        // painting only went into the empty cells
+        assert!(count(&world, ParticleType::SAND) == 100 && count(&world, ParticleType::WATER) == 100);
+
+        let mut replace = Tool::build(ParticleType::STONE, Shape::Rect, Mode::Replace);
+        drag(&mut replace, &mut world, &[(0, 0), (19, 4)]);
+        assert!(count(&world, ParticleType::STONE) == 50);
+        assert!(count(&world, ParticleType::SAND) == 50 && count(&world, ParticleType::WATER) == 100);
+
+        let mut erase = Tool::build(ParticleType::STONE, Shape::Square, Mode::Erase);
+        world.cluster_size = 41;
+        drag(&mut erase, &mut world, &[(20, 15)]);
+        assert!(count(&world, ParticleType::EMPTY) == 40 * 30);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::chunks::Chunk;
+use crate::sandbox::Handler;
+use crate::sandbox::SandBox;
+
+/// side of one air cell in particles
+pub const WIND_CELL: usize = 8;
+
+/// gauss-seidel sweeps for the pressure solve, plenty at this resolution
+const PRESSURE_ITERATIONS: usize = 20;
+
+/// share of the air speed lost every tick, so the wind dies down once the fans
+/// are gone
+const DAMPING: f32 = 0.01;
+
+/// air slower than this counts as still and leaves settled tiles alone
+const CALM: f32 = 0.05;
+
+/// a resting particle gets picked up once the push on it gets past this
+const LIFT: f32 = 0.5;
+
+/// coarse air velocity field laid over the sandbox, solved with a stable
+/// fluids step every tick. fans hold the air in their cells at a fixed speed
+/// and static bodies block it
+pub struct WindField {
+    pub width: usize,
+    pub height: usize,
+    pub(crate) vx: Vec<f32>,
+    pub(crate) vy: Vec<f32>,
+    pub(crate) fans: Vec<Option<(f32, f32)>>,
+    solid: Vec<bool>,
+    links: Vec<[usize; 4]>,
+    pressure: Vec<f32>,
+    divergence: Vec<f32>,
+    scratch_x: Vec<f32>,
+    scratch_y: Vec<f32>,
+    pub(crate) calm: bool,
+}
+
+impl WindField {
+    pub fn build(width: usize, height: usize) -> Self {
+        let (width, height) = (width.div_ceil(WIND_CELL), height.div_ceil(WIND_CELL));
+        let area = width * height;
+        WindField {
+            width,
+            height,
+            vx: vec![0.; area],
+            vy: vec![0.; area],
+            fans: vec![None; area],
+            solid: vec![false; area],
+            links: vec![[0; 4]; area],
+            pressure: vec![0.; area],
+            divergence: vec![0.; area],
+            scratch_x: vec![0.; area],
+            scratch_y: vec![0.; area],
+            calm: true,
+        }
+    }
+
+    /// air velocity in particles per tick around a particle position
+    pub fn at(&self, x: usize, y: usize) -> (f32, f32) {
+        let idx = self.cell(x, y);
+        (self.vx[idx], self.vy[idx])
+    }
+
+    pub fn fan(&self, x: usize, y: usize) -> Option<(f32, f32)> {
+        self.fans[self.cell(x, y)]
+    }
+
+    /// puts a fan blowing at the given speed into the air cell under a
+    /// particle position, or takes it out again with `None`
+    pub fn set_fan(&mut self, x: usize, y: usize, fan: Option<(f32, f32)>) {
+        let idx = self.cell(x, y);
+        self.fans[idx] = fan;
+        self.calm = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.fans.fill(None);
+        self.vx.fill(0.);
+        self.vy.fill(0.);
+        self.calm = true;
+    }
+
+    pub fn is_calm(&self) -> bool {
+        self.calm
+    }
+
+    pub(crate) fn cell(&self, x: usize, y: usize) -> usize {
+        (y / WIND_CELL).min(self.height - 1) * self.width + (x / WIND_CELL).min(self.width - 1)
+    }
+
+    /// the four neighbours of a cell, anything off the grid or blocked reads
+    /// as the cell itself so walls push back instead of leaking
+    fn neighbours(&self, idx: usize) -> [usize; 4] {
+        let (x, y) = (idx % self.width, idx / self.width);
+        let open = |nx: usize, ny: usize| {
+            if nx < self.width && ny < self.height && !self.solid[ny * self.width + nx] {
+                ny * self.width + nx
+            }
+            else {
+                idx
+            }
+        };
+        [open(x.wrapping_sub(1), y), open(x + 1, y), open(x, y.wrapping_sub(1)), open(x, y + 1)]
+    }
+
+    /// one stable fluids step. advects the air along itself, damps it, then
+    /// projects out the divergence so it flows around obstacles instead of
+    /// piling up against them
+    fn step(&mut self) {
+        let (width, height) = (self.width, self.height);
+        (0..width * height).for_each(|idx| self.links[idx] = self.neighbours(idx));
+        self.hold();
+
+        (0..width * height).for_each(|idx| {
+            let (x, y) = (idx % width, idx / width);
+            let back_x = (x as f32 - self.vx[idx] / WIND_CELL as f32).clamp(0., (width - 1) as f32);
+            let back_y = (y as f32 - self.vy[idx] / WIND_CELL as f32).clamp(0., (height - 1) as f32);
+            self.scratch_x[idx] = self.sample(&self.vx, back_x, back_y) * (1. - DAMPING);
+            self.scratch_y[idx] = self.sample(&self.vy, back_x, back_y) * (1. - DAMPING);
+        });
+        std::mem::swap(&mut self.vx, &mut self.scratch_x);
+        std::mem::swap(&mut self.vy, &mut self.scratch_y);
+        self.hold();
+
+        (0..width * height).for_each(|idx| {
+            let [left, right, up, down] = self.links[idx];
+            // a wall has no air moving through it
+            let open = |cell: usize| (cell != idx) as u8 as f32;
+            let (vx, vy) = (&self.vx, &self.vy);
+            self.divergence[idx] = -0.5
+                * (vx[right] * open(right) - vx[left] * open(left) + vy[down] * open(down) - vy[up] * open(up));
+        });
+        self.pressure.fill(0.);
+        (0..PRESSURE_ITERATIONS).for_each(|_| {
+            (0..width * height).for_each(|idx| {
+                let sum: f32 = self.links[idx].iter().map(|&cell| self.pressure[cell]).sum();
+                self.pressure[idx] = (self.divergence[idx] + sum) / 4.;
+            });
+        });
+        (0..width * height).for_each(|idx| {
+            let [left, right, up, down] = self.links[idx];
+            self.vx[idx] -= 0.5 * (self.pressure[right] - self.pressure[left]);
+            self.vy[idx] -= 0.5 * (self.pressure[down] - self.pressure[up]);
+        });
+        self.hold();
+
+        let fastest = self.vx.iter().zip(&self.vy).map(|(vx, vy)| vx.abs() + vy.abs()).fold(0., f32::max);
+        self.calm = fastest < CALM && self.fans.iter().all(Option::is_none);
+        if self.calm {
+            self.vx.fill(0.);
+            self.vy.fill(0.);
+        }
+    }
+
+    /// fans keep blowing at their own speed and nothing moves inside a wall
+    fn hold(&mut self) {
+        (0..self.width * self.height).for_each(|idx| {
+            if self.solid[idx] {
+                (self.vx[idx], self.vy[idx]) = (0., 0.);
+            }
+            else if let Some((vx, vy)) = self.fans[idx] {
+                (self.vx[idx], self.vy[idx]) = (vx, vy);
+            }
+        });
+    }
+
+    fn sample(&self, field: &[f32], x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+        let row = |y: usize| field[y * self.width + x0] * (1. - tx) + field[y * self.width + x1] * tx;
+        row(y0) * (1. - ty) + row(y1) * ty
+    }
+}
+
+impl SandBox {
+    /// paints fans blowing at the given speed over a brush sized area
+    pub fn add_fan(&mut self, x: usize, y: usize, vx: f32, vy: f32) {
+        self.paint_fans(x, y, Some((vx, vy)));
+    }
+
+    pub fn remove_fans(&mut self, x: usize, y: usize) {
+        self.paint_fans(x, y, None);
+    }
+
+    fn paint_fans(&mut self, x: usize, y: usize, fan: Option<(f32, f32)>) {
+        let bounds = (self.cluster_size / 2) as isize;
+        (-bounds..=bounds).step_by(WIND_CELL / 2).for_each(|dy| {
+            (-bounds..=bounds).step_by(WIND_CELL / 2).for_each(|dx| {
+                let (nx, ny) = (x.saturating_add_signed(dx), y.saturating_add_signed(dy));
+                if dx * dx + dy * dy <= bounds * bounds && nx < self.width && ny < self.height {
+                    self.history.record_fan(self.wind.cell(nx, ny), self.wind.fan(nx, ny));
+                    self.wind.set_fan(nx, ny, fan);
+                }
+            });
+        });
+    }
+
+    /// moves the air on by a tick and wakes everything it's blowing hard
+    /// enough over to pick up whatever settled there. anything lighter than
+    /// that is either moving already or wouldn't budge anyway, so the rest of
+    /// the world can stay asleep
+    pub(crate) fn blow_wind(&mut self) {
+        if self.wind.is_calm() {
+            return;
+        }
+        let lightest = self.materials.iter().map(|(_, material)| material.params.wind).fold(0., f32::max);
+        let lifting = LIFT / lightest.max(f32::EPSILON);
+        let (width, height) = (self.wind.width, self.wind.height);
+        (0..width * height).for_each(|idx| {
+            let x = ((idx % width) * WIND_CELL + WIND_CELL / 2).min(self.width - 1);
+            let y = ((idx / width) * WIND_CELL + WIND_CELL / 2).min(self.height - 1);
+            self.wind.solid[idx] = self.is_static(&self.grid[y * self.width + x]);
+        });
+        self.wind.step();
+
+        (0..width * height).filter(|&idx| self.wind.vx[idx].abs() + self.wind.vy[idx].abs() > lifting).for_each(|idx| {
+            let (x, y) = ((idx % width) * WIND_CELL, (idx / width) * WIND_CELL);
+            self.tiles.wake_area(Chunk::build(x, x + WIND_CELL, y, y + WIND_CELL));
+        });
+    }
+}
+
+impl Handler<'_> {
+    /// the air pushes on anything light enough to care, resting particles get
+    /// knocked loose once it's strong enough
+    pub fn blow(&mut self) {
+        let params = self.get_params_here();
+        if params.wind <= 0. {
+            return;
+        }
+        let (wx, wy) = self.sandbox.deref().wind.at(self.x, self.y);
+        let strength = (wx.abs() + wy.abs()) * params.wind;
+        if strength <= 0. {
+            return;
+        }
+
+        let limit = params.terminal_velocity;
+        let here = self.get_mut_here();
+        here.vx = (here.vx + wx * params.wind).clamp(-limit, limit);
+        here.vy = (here.vy + wy * params.wind).clamp(-limit, limit);
+        if here.is_solid() && strength > LIFT {
+            here.awake = true;
+            here.begin_falling();
+        }
+        self.reup_here();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particles::ParticleType;
+    use crate::sandbox::SandBox;
+
+    /// a strip of ash lying on the floor with a fan to the left of it
+    fn breezy(fan: bool) -> SandBox {
+        let mut world = SandBox::build_seeded(160, 48, 1);
+        (20..40).for_each(|x| world.put_particle(ParticleType::ASH, x, 47));
+        if fan {
+            world.add_fan(8, 40, 4., 0.);
+        }
+        world
+    }
+
+    fn mean_x(world: &SandBox, species: ParticleType) -> f32 {
+        let cells: Vec<usize> = (0..world.grid.len()).filter(|&idx| world.grid[idx].species == species).collect();
+        cells.iter().map(|&idx| (idx % world.width) as f32).sum::<f32>() / cells.len() as f32
+    }
+
+    #[test]
+    fn fans_blow_light_particles_downwind() {
+        let (mut still, mut blown) = (breezy(false), breezy(true));
+        (0..200).for_each(|_| {
+            still.update();
+            blown.update();
+        });
+        assert!(mean_x(&blown, ParticleType::ASH) > mean_x(&still, ParticleType::ASH) + 5.);
+        assert!(blown.census()[ParticleType::ASH.index()] == 20);
+    }
+
+    #[test]
+    fn static_bodies_block_the_air() {
+        let (mut open, mut walled) = (breezy(true), breezy(true));
+        // a full air cell thick, the air only looks at the middle of each
+        (0..48).for_each(|y| (80..88).for_each(|x| walled.put_particle(ParticleType::STONE, x, y)));
+        (0..100).for_each(|_| {
+            open.update();
+            walled.update();
+        });
+        let behind = |world: &SandBox| (0..48).map(|y| world.wind.at(120, y).0.abs()).fold(0., f32::max);
+        assert!(behind(&open) > 0.1);
+        assert!(behind(&walled) < behind(&open) / 10.);
+    }
+
+    #[test]
+    fn the_air_settles_once_the_fans_are_gone() {
+        let mut world = SandBox::build_seeded(160, 48, 1);
+        world.add_fan(8, 40, 4., 0.);
+        (0..20).for_each(|_| world.update());
+        assert!(!world.wind.is_calm());
+        world.remove_fans(8, 40);
+        let mut ticks = 0;
+        while !world.wind.is_calm() && ticks < 1000 {
+            world.update();
+            ticks += 1;
+        }
+        assert!(world.wind.is_calm());
+        assert!((0..48).all(|y| world.wind.at(80, y) == (0., 0.)));
+        // an empty world has nothing else to keep it awake
+        (0..2).for_each(|_| world.update());
+        assert!(world.tiles.rects().iter().all(|&(current, next)| current.is_none() && next.is_none()));
+    }
+}